@@ -0,0 +1,306 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use bstr::ByteSlice;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    Result,
+    instance::{self, Instance},
+    interface,
+    parsing::*,
+    patterns::Fields,
+};
+
+/// Number of parsed events kept for `/events`.
+pub const RECENT_EVENTS: usize = 100;
+
+/// Something the server logged that is relayed to Discord, as reported by `/events`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    /// Seconds since the Unix epoch at which the event was relayed.
+    pub time: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    Chat {
+        player: Box<str>,
+        message: Box<str>,
+    },
+    Emote {
+        player: Box<str>,
+        action: Box<str>,
+    },
+    Say {
+        sender: Box<str>,
+        message: Box<str>,
+    },
+    TeamChat {
+        team: Box<str>,
+        player: Box<str>,
+        message: Box<str>,
+    },
+    Join {
+        player: Box<str>,
+    },
+    Leave {
+        player: Box<str>,
+    },
+    Disconnect {
+        player: Box<str>,
+        reason: Box<str>,
+    },
+    Moderation {
+        action: &'static str,
+        /// Player who ran the command, or `None` if it was run from the console.
+        actor: Option<Box<str>>,
+        player: Box<str>,
+        reason: Option<Box<str>>,
+    },
+    Advancement {
+        player: Box<str>,
+        message: Box<str>,
+    },
+    Death {
+        player: Box<str>,
+        message: Box<str>,
+    },
+    Starting {
+        version: Box<str>,
+    },
+    Ready {
+        duration: f64,
+    },
+    /// A line matched by a pattern from `PATTERNS_FILE`.
+    Custom {
+        name: Box<str>,
+        fields: Fields,
+    },
+}
+
+impl EventKind {
+    /// Converts a parsed log into an event, where `message` is the part of the line the log was
+    /// parsed from.
+    pub fn from_log(log: &Log<'_>, message: &[u8]) -> Option<Self> {
+        let text = |s: &[u8]| -> Box<str> { s.to_str_lossy().into() };
+
+        Some(match log {
+            Log::Chat(ChatLog {
+                sender, message, ..
+            }) => EventKind::Chat {
+                player: text(sender),
+                message: text(message),
+            },
+            Log::Emote(EmoteLog { player, action, .. }) => EventKind::Emote {
+                player: text(player),
+                action: text(action),
+            },
+            Log::Say(SayLog {
+                sender, message, ..
+            }) => EventKind::Say {
+                sender: text(sender),
+                message: text(message),
+            },
+            Log::TeamChat(TeamChatLog {
+                team,
+                sender,
+                message,
+                ..
+            }) => EventKind::TeamChat {
+                team: text(team),
+                player: text(sender),
+                message: text(message),
+            },
+            // Whispers are private, so they are left out
+            Log::Join(JoinLog { player, .. }) => EventKind::Join {
+                player: text(player),
+            },
+            Log::Leave(LeaveLog { player, .. }) => EventKind::Leave {
+                player: text(player),
+            },
+            Log::Disconnect(DisconnectLog { player, reason, .. }) => EventKind::Disconnect {
+                player: text(player),
+                reason: text(reason),
+            },
+            Log::Moderation(ModerationLog { actor, action, .. }) => EventKind::Moderation {
+                action: action.name(),
+                actor: actor.map(text),
+                player: text(action.player()),
+                reason: action.reason().map(text),
+            },
+            Log::Advancement(AdvancementLog { player, .. }) => EventKind::Advancement {
+                player: text(player),
+                message: text(message),
+            },
+            Log::Death(DeathLog { victim, .. }) => EventKind::Death {
+                player: text(victim),
+                message: text(message),
+            },
+            Log::Starting(StartingLog { version, .. }) => EventKind::Starting {
+                version: text(version),
+            },
+            Log::Ready(ReadyLog { duration, .. }) => EventKind::Ready {
+                duration: *duration,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Records an event for `/events`, forgetting the oldest once [`RECENT_EVENTS`] are kept.
+pub fn record(server: &Instance, kind: EventKind) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+
+    let mut events = server.events.lock();
+    if events.len() == RECENT_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(Event { time, kind });
+}
+
+/// Serves the HTTP API on `address`. Commands can only be sent with `token`, and are refused if
+/// there is none.
+pub async fn serve(address: SocketAddr, token: Option<String>) -> Result<()> {
+    if !address.ip().is_loopback() {
+        eprintln!(
+            "Warning: the HTTP API is listening on {address}, which is not a loopback address"
+        );
+    }
+
+    let app = Router::new()
+        .route("/state", get(state))
+        .route("/players", get(players))
+        .route("/events", get(events))
+        .route("/command", post(command))
+        .with_state(token.map(Arc::<str>::from));
+
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    eprintln!("Serving the HTTP API on {address}");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+/// Selects the server a request applies to, defaulting to the first.
+#[derive(Deserialize)]
+struct ServerQuery {
+    server: Option<String>,
+}
+
+impl ServerQuery {
+    fn resolve(&self) -> Result<&'static Instance, ApiError> {
+        match &self.server {
+            Some(name) => instance::get(name).ok_or_else(|| {
+                ApiError(
+                    StatusCode::NOT_FOUND,
+                    format!("There is no server named {name}."),
+                )
+            }),
+            None => Ok(instance::default()),
+        }
+    }
+}
+
+struct ApiError(StatusCode, String);
+
+impl From<eyre::Report> for ApiError {
+    fn from(e: eyre::Report) -> Self {
+        ApiError(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+async fn state(Query(query): Query<ServerQuery>) -> Result<Json<Value>, ApiError> {
+    let server = query.resolve()?;
+
+    Ok(Json(json!({
+        "server": &*server.name,
+        "state": server.state().as_str(),
+        "version": server.version.lock().as_deref(),
+        "response_time_ms": server.round_trip.lock().map(|round_trip| round_trip.as_millis() as u64),
+    })))
+}
+
+async fn players(Query(query): Query<ServerQuery>) -> Result<Json<Value>, ApiError> {
+    let list = interface::list(query.resolve()?, interface::RESPONSE_TIMEOUT).await?;
+    let players = list
+        .players
+        .iter()
+        .map(|player| json!({ "name": &*player.name, "uuid": player.uuid.to_string() }))
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({ "players": players, "max": list.max })))
+}
+
+async fn events(Query(query): Query<ServerQuery>) -> Result<Json<VecDeque<Event>>, ApiError> {
+    Ok(Json(query.resolve()?.events.lock().clone()))
+}
+
+/// Runs the request body as a command and responds with the log lines that follow it.
+async fn command(
+    State(token): State<Option<Arc<str>>>,
+    Query(query): Query<ServerQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<Value>, ApiError> {
+    let Some(token) = token else {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            String::from("Commands are disabled because API_TOKEN is not set."),
+        ));
+    };
+
+    // Compared in constant time so the token cannot be guessed from response times
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| given.as_bytes().ct_eq(token.as_bytes()).into());
+
+    if !authorized {
+        return Err(ApiError(
+            StatusCode::UNAUTHORIZED,
+            String::from("A valid bearer token is required."),
+        ));
+    }
+
+    let command = body.trim();
+    if command.is_empty() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            String::from("The request body should be a command."),
+        ));
+    }
+
+    let server = query.resolve()?;
+    server.wait_until_running().await?;
+
+    let output = interface::output(server, command.as_bytes()).await?;
+    Ok(Json(
+        json!({ "output": output.iter().map(|line| &**line).collect::<Vec<_>>() }),
+    ))
+}
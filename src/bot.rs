@@ -2,15 +2,21 @@ mod crash;
 mod download;
 mod list;
 mod nbtq;
+mod restart;
+mod start;
+mod status;
+mod stop;
+mod threaddump;
 mod tpo;
 
 use std::{
     borrow::Cow,
     fmt::Display,
-    path::{Path, PathBuf},
+    path::PathBuf,
+    time::{Duration, Instant},
 };
 
-use parking_lot::Mutex;
+use eyre::{bail, eyre};
 use poise::{
     CreateReply, FrameworkError,
     serenity_prelude::{self as serenity, CacheHttp, GatewayIntents, RoleId},
@@ -19,17 +25,42 @@ use serde::Deserialize;
 use uuid::Uuid;
 use walkdir::WalkDir;
 
-use crate::{Error, Result};
+use crate::{
+    Error, Result,
+    instance::{self, Instance},
+};
 
 pub struct Data {
-    pub bot_start_notifier: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
-    pub server_directory: Box<Path>,
     pub operator_role_id: RoleId,
 }
 
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 
-pub async fn start_bot(bot_start_notifier: tokio::sync::oneshot::Sender<()>) -> Result<()> {
+/// Runs the bot, starting it again whenever it stops, such as when Discord is unreachable or the
+/// token is rejected.
+pub async fn run() -> Result<()> {
+    const MIN_BACKOFF: Duration = Duration::from_secs(5);
+    const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let started = Instant::now();
+        if let Err(e) = start_bot().await {
+            eprintln!("Discord bot stopped: {e}");
+        }
+
+        // A bot that ran for a while stopped for a new reason
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+
+        eprintln!("Restarting Discord bot in {}s", backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn start_bot() -> Result<()> {
     let token = crate::env::discord_bot_token();
     let intents = GatewayIntents::non_privileged()
         | GatewayIntents::MESSAGE_CONTENT
@@ -43,6 +74,11 @@ pub async fn start_bot(bot_start_notifier: tokio::sync::oneshot::Sender<()>) ->
                 download::download(),
                 list::list(),
                 nbtq::nbtq(),
+                start::start(),
+                stop::stop(),
+                restart::restart(),
+                threaddump::threaddump(),
+                status::status(),
             ],
             event_handler: |ctx, event, framework, data| {
                 Box::pin(event_handler(ctx, event, framework, data))
@@ -73,8 +109,6 @@ pub async fn start_bot(bot_start_notifier: tokio::sync::oneshot::Sender<()>) ->
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
                 Ok(Data {
-                    bot_start_notifier: Mutex::new(Some(bot_start_notifier)),
-                    server_directory: crate::server_directory().into(),
                     operator_role_id: crate::env::discord_operator_role_id().into(),
                 })
             })
@@ -99,17 +133,15 @@ async fn event_handler(
     match event {
         serenity::FullEvent::Ready { data_about_bot, .. } => {
             eprintln!("Logged in as {}", data_about_bot.user.name);
-            data.bot_start_notifier
-                .lock()
-                .take()
-                .unwrap()
-                .send(())
-                .unwrap();
         }
         serenity::FullEvent::Message { new_message }
             if !new_message.author.bot && new_message.thread.is_none() =>
         {
-            if new_message.channel_id.get() == crate::env::discord_channel_id() {
+            let Some(server) = instance::by_channel(new_message.channel_id.get()) else {
+                return Ok(());
+            };
+
+            if new_message.channel_id.get() == server.channel_id {
                 const PREFIX: &str = "[Discord] ";
                 let author = new_message
                     .author_nick(ctx.http())
@@ -127,15 +159,18 @@ async fn event_handler(
                 content.push_str("> ");
                 content.push_str(&new_message.content);
 
-                crate::command(format!(r#"tellraw @a {{"text":{:?}}}"#, content).as_bytes())
+                server
+                    .command_when_running(
+                        format!(r#"tellraw @a {{"text":{:?}}}"#, content).as_bytes(),
+                    )
                     .await?;
-            } else if new_message.channel_id.get() == crate::env::discord_console_channel_id()
+            } else if new_message.channel_id.get() == server.console_channel_id
                 && new_message
                     .member(&ctx.http)
                     .await
                     .is_ok_and(|m| m.roles.contains(&data.operator_role_id))
             {
-                crate::command(new_message.content.as_bytes()).await?;
+                server.command(new_message.content.as_bytes()).await?;
             }
         }
         _ => {}
@@ -191,6 +226,50 @@ pub async fn is_operator(ctx: Context<'_>) -> Result<bool> {
     }
 }
 
+/// Resolves the server a command applies to: the one named, otherwise the one whose channel the
+/// command was used in, otherwise the only one.
+pub fn server(ctx: Context<'_>, name: Option<&str>) -> Result<&'static Instance> {
+    if let Some(name) = name {
+        return instance::get(name).ok_or_else(|| eyre!("There is no server named {name}."));
+    }
+
+    if let Some(server) = instance::by_channel(ctx.channel_id().get()) {
+        return Ok(server);
+    }
+
+    match instance::all() {
+        [server] => Ok(server),
+        _ => bail!("Please choose a server."),
+    }
+}
+
+pub async fn autocomplete_server<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = &'static str> + 'a {
+    instance::all()
+        .iter()
+        .map(|instance| &*instance.name)
+        .filter(move |name| name.contains(partial))
+}
+
+/// Resolves the server chosen in the `server` option while another option is being
+/// autocompleted.
+fn selected_server(ctx: Context<'_>) -> Option<&'static Instance> {
+    let name = match ctx {
+        poise::Context::Application(ctx) => ctx
+            .interaction
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == "server")
+            .and_then(|option| option.value.as_str()),
+        poise::Context::Prefix(_) => None,
+    };
+
+    server(ctx, name).ok()
+}
+
 async fn autocomplete_path(
     ctx: Context<'_>,
     partial: &str,
@@ -200,6 +279,10 @@ async fn autocomplete_path(
         return Vec::new();
     }
 
+    let Some(server) = selected_server(ctx) else {
+        return Vec::new();
+    };
+
     let mut path = PathBuf::from(partial);
     if path
         .components()
@@ -208,7 +291,8 @@ async fn autocomplete_path(
         return Vec::new();
     }
 
-    let Some(mut root) = crate::server_directory()
+    let Some(mut root) = server
+        .server_directory
         .canonicalize()
         .ok()
         .and_then(|d| d.to_str().map(|s| s.to_string()))
@@ -218,6 +302,9 @@ async fn autocomplete_path(
 
     root.push('/');
 
+    // Paths are relative to the selected server rather than the working directory
+    path = server.server_directory.join(path);
+
     if matches!(std::fs::exists(&path), Ok(true)) {
         if !path.is_dir() {
             return vec![partial.to_string()];
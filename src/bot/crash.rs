@@ -8,8 +8,14 @@ use crate::Result;
 
 /// Get the latest crash log
 #[poise::command(slash_command, guild_only, check = "super::is_operator")]
-pub async fn crash(ctx: Context<'_>) -> Result<()> {
-    let path = ctx.data().server_directory.join("crash-reports");
+pub async fn crash(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    let path = server.server_directory.join("crash-reports");
 
     let (name, bytes) = tokio::task::spawn_blocking(move || {
         let dir = fs::read_dir(&path)?;
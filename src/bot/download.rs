@@ -19,8 +19,12 @@ pub async fn download(
     #[description = "Path to the file or folder"]
     #[autocomplete = "super::autocomplete_path_any"]
     path: String,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
 ) -> Result<()> {
-    let path = ctx.data().server_directory.safe_join(path)?;
+    let server = super::server(ctx, server.as_deref())?;
+    let path = server.server_directory.safe_join(path)?;
     let Some(mut name) = path.file_name().map(|s| s.to_string_lossy().into_owned()) else {
         bail!("Requested file has no name");
     };
@@ -5,12 +5,16 @@ use crate::{Result, interface};
 
 /// Get a list of players
 #[poise::command(slash_command, guild_only, check = "super::is_operator")]
-pub async fn list(ctx: Context<'_>) -> Result<()> {
-    let list = {
-        let mut recv = interface::LIST_SENDER.get().unwrap().subscribe();
-        interface::list().await?;
-        recv.recv().await?
-    };
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    ctx.defer_ephemeral().await?;
+
+    let list = interface::list(server, interface::RESPONSE_TIMEOUT).await?;
 
     if list.players.is_empty() {
         ctx.send(
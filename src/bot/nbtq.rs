@@ -26,9 +26,13 @@ pub async fn nbtq(
     #[autocomplete = "super::autocomplete_path_nbt"]
     path: String,
     #[description = "Save output of filter to the input file"] save: Option<bool>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
 ) -> Result<(), Error> {
+    let server = super::server(ctx, server.as_deref())?;
     let save = save.unwrap_or(false);
-    let path = ctx.data().server_directory.safe_join(path)?;
+    let path = server.server_directory.safe_join(path)?;
     let file_name = path
         .file_name()
         .map(|s| s.to_string_lossy().to_string())
@@ -0,0 +1,38 @@
+use eyre::bail;
+use poise::CreateReply;
+
+use super::Context;
+use crate::{
+    Result,
+    instance::Mode,
+    supervisor::{Request, State},
+};
+
+/// Restart the server
+#[poise::command(slash_command, guild_only, check = "super::is_operator")]
+pub async fn restart(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    if matches!(server.mode, Mode::Rcon { .. }) {
+        bail!("This server is not started by mcglue.");
+    }
+
+    if !matches!(server.state(), State::Starting | State::Running) {
+        bail!("The server is not running.");
+    }
+
+    server.request(Request::Restart).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content("Restarting the server."),
+    )
+    .await?;
+
+    Ok(())
+}
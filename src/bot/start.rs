@@ -0,0 +1,38 @@
+use eyre::bail;
+use poise::CreateReply;
+
+use super::Context;
+use crate::{
+    Result,
+    instance::Mode,
+    supervisor::{Request, State},
+};
+
+/// Start the server
+#[poise::command(slash_command, guild_only, check = "super::is_operator")]
+pub async fn start(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    if matches!(server.mode, Mode::Rcon { .. }) {
+        bail!("This server is not started by mcglue.");
+    }
+
+    if server.state() != State::Stopped {
+        bail!("The server is already running.");
+    }
+
+    server.request(Request::Start).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content("Starting the server."),
+    )
+    .await?;
+
+    Ok(())
+}
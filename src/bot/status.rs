@@ -0,0 +1,77 @@
+use std::fmt::Write;
+
+use poise::CreateReply;
+
+use super::Context;
+use crate::{
+    Result, interface,
+    monitor::{self, format_bytes, format_duration},
+    supervisor::State,
+};
+
+/// Get the status and resource usage of the server
+#[poise::command(slash_command, guild_only, check = "super::is_operator")]
+pub async fn status(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    ctx.defer_ephemeral().await?;
+
+    let mut status = format!("The server is {}", server.state().as_str());
+    if let Some(version) = server.version.lock().as_deref() {
+        write!(status, " on version {version}")?;
+    }
+    status.push('.');
+
+    if server.state() == State::Running {
+        match interface::list(server, interface::RESPONSE_TIMEOUT).await {
+            Ok(list) => write!(status, "\nPlayers: {}/{}", list.players.len(), list.max)?,
+            Err(_) => status.push_str("\nPlayers: unknown"),
+        }
+
+        if let Some(round_trip) = *server.round_trip.lock() {
+            write!(status, "\nResponse time: {}ms", round_trip.as_millis())?;
+        }
+    }
+
+    if server.process().is_some() {
+        match monitor::usage(server).await {
+            Ok(usage) => {
+                write!(status, "\nUptime: {}", format_duration(usage.uptime))?;
+                write!(status, "\nMemory: {}", format_bytes(usage.rss))?;
+                if let (Some(max_heap), Some(percent)) = (usage.max_heap, usage.rss_percent()) {
+                    write!(
+                        status,
+                        " ({percent:.0}% of {} -Xmx)",
+                        format_bytes(max_heap)
+                    )?;
+                }
+                write!(status, "\nCPU: {:.1}%", usage.cpu)?;
+                write!(status, "\nThreads: {}", usage.threads)?;
+                write!(status, "\nOpen files: {}", usage.fds)?;
+            }
+            Err(e) => write!(status, "\nResource usage unavailable: {e}")?,
+        }
+    }
+
+    write!(
+        status,
+        "\nUndelivered messages: {} chat, {} console",
+        server.webhook.backlog(),
+        server.console_webhook.backlog()
+    )?;
+    if let Some(staff_webhook) = &server.staff_webhook {
+        write!(status, ", {} staff", staff_webhook.backlog())?;
+    }
+    if let Some(audit_webhook) = &server.audit_webhook {
+        write!(status, ", {} audit", audit_webhook.backlog())?;
+    }
+
+    ctx.send(CreateReply::default().ephemeral(true).content(status))
+        .await?;
+
+    Ok(())
+}
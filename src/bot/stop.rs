@@ -0,0 +1,33 @@
+use eyre::bail;
+use poise::CreateReply;
+
+use super::Context;
+use crate::{
+    Result,
+    supervisor::{Request, State},
+};
+
+/// Stop the server
+#[poise::command(slash_command, guild_only, check = "super::is_operator")]
+pub async fn stop(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    if !matches!(server.state(), State::Starting | State::Running) {
+        bail!("The server is not running.");
+    }
+
+    server.request(Request::Stop).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content("Stopping the server."),
+    )
+    .await?;
+
+    Ok(())
+}
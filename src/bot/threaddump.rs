@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use chrono::Local;
+use poise::{CreateReply, serenity_prelude::CreateAttachment};
+
+use super::Context;
+use crate::Result;
+
+/// Get a thread dump of the server
+#[poise::command(slash_command, guild_only, check = "super::is_operator")]
+pub async fn threaddump(
+    ctx: Context<'_>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<()> {
+    let server = super::server(ctx, server.as_deref())?;
+    ctx.defer_ephemeral().await?;
+    server.wait_until_running().await?;
+
+    let dump = crate::threaddump::request(server, Duration::from_secs(10)).await?;
+    let name = format!(
+        "threaddump-{}.txt",
+        Local::now().format("%Y-%m-%d_%H.%M.%S")
+    );
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .attachment(CreateAttachment::bytes(dump.as_bytes(), name)),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -35,10 +35,16 @@ pub async fn tpo(
     #[description = "Dimension ID"]
     #[autocomplete = "autocomplete_dimension"]
     dimension: Option<String>,
+    #[description = "Server"]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
 ) -> Result<(), Error> {
+    let server = super::server(ctx, server.as_deref())?;
+    ctx.defer_ephemeral().await?;
+
     let uuid = super::maybe_username_to_uuid(&player).await?;
 
-    let list = crate::interface::list().await?;
+    let list = crate::interface::list(server, crate::interface::RESPONSE_TIMEOUT).await?;
 
     if let Some(p) = list
         .players
@@ -56,7 +62,7 @@ pub async fn tpo(
     let mut filename = uuid.as_hyphenated().to_string();
     filename.push_str(".dat");
     let path = {
-        ctx.data()
+        server
             .server_directory
             .join("world")
             .join("playerdata")
@@ -0,0 +1,283 @@
+use std::{
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use eyre::{bail, eyre};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream, unix::OwnedWriteHalf},
+    sync::broadcast::error::RecvError,
+};
+
+use crate::{
+    Result,
+    instance::{self, Instance},
+    interface,
+};
+
+const USAGE: &str = "Usage: mcglue send [--server <name>] <command>
+       mcglue list [--server <name>]
+       mcglue tail [--server <name>]";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Send a command and return the log lines that follow it.
+    Send {
+        server: Option<String>,
+        command: String,
+    },
+    List {
+        server: Option<String>,
+    },
+    /// Stream log lines until the client disconnects.
+    Tail {
+        server: Option<String>,
+    },
+}
+
+impl Request {
+    /// Parses the arguments mcglue was given, returning `None` if they are not a client
+    /// subcommand.
+    pub fn from_args(args: &[String]) -> Option<Result<Self>> {
+        let (subcommand, rest) = args.split_first()?;
+        if !matches!(subcommand.as_str(), "send" | "list" | "tail") {
+            return None;
+        }
+
+        let (server, rest) = match rest {
+            [flag, name, rest @ ..] if flag == "-s" || flag == "--server" => {
+                (Some(name.clone()), rest)
+            }
+            _ => (None, rest),
+        };
+
+        Some(match (subcommand.as_str(), rest) {
+            ("send", []) => Err(eyre!(USAGE)),
+            ("send", command) => Ok(Request::Send {
+                server,
+                command: command.join(" "),
+            }),
+            ("list", []) => Ok(Request::List { server }),
+            ("tail", []) => Ok(Request::Tail { server }),
+            _ => Err(eyre!(USAGE)),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Line(Box<str>),
+    Error(String),
+}
+
+pub fn socket_path() -> PathBuf {
+    PathBuf::from(crate::env::control_socket().unwrap_or_else(|| String::from("mcglue.sock")))
+}
+
+/// Sends a request to a running mcglue and prints its response.
+pub async fn client(request: Request) -> Result<()> {
+    let path = socket_path();
+    let stream = UnixStream::connect(&path)
+        .await
+        .map_err(|e| eyre!("Could not connect to {path:?}, is mcglue running? {e}"))?;
+
+    let (reader, mut writer) = stream.into_split();
+    let mut buf = serde_json::to_vec(&request)?;
+    buf.push(b'\n');
+    writer.write_all(&buf).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        match serde_json::from_str::<Response>(&line)? {
+            Response::Line(line) => print!("{line}"),
+            Response::Error(e) => bail!(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Accepts client connections on the control socket.
+pub async fn listen(path: PathBuf) -> Result<()> {
+    remove_stale(&path)?;
+
+    let listener = bind(&path)?;
+    eprintln!("Listening for commands on {path:?}");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream).await {
+                eprintln!("Error handling control socket client: {e:?}");
+            }
+        });
+    }
+}
+
+/// Binds the socket so that only mcglue's user can connect to it, since it gives the same access
+/// as the console channel. Binding creates the socket with the umask's permissions, so it is
+/// bound in a private directory and only moved into place once it is restricted.
+fn bind(path: &Path) -> Result<UnixListener> {
+    let mut private = path.as_os_str().to_owned();
+    private.push(format!(".{}", std::process::id()));
+    let private = PathBuf::from(private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+
+    let socket = private.join("socket");
+    let listener = UnixListener::bind(&socket).and_then(|listener| {
+        std::fs::set_permissions(&socket, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&socket, path)?;
+        Ok(listener)
+    });
+
+    let _ = std::fs::remove_file(&socket);
+    let _ = std::fs::remove_dir(&private);
+    Ok(listener?)
+}
+
+/// Removes a socket left behind by a previous run, refusing to touch anything else.
+pub fn remove_stale(path: &Path) -> Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+        Ok(_) => bail!("{path:?} exists and is not a socket"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn handle(stream: UnixStream) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let request = serde_json::from_str::<Request>(&line)?;
+
+    if let Err(e) = respond(request, &mut writer).await {
+        write(&mut writer, &Response::Error(e.to_string())).await?;
+    }
+
+    Ok(())
+}
+
+async fn respond(request: Request, writer: &mut OwnedWriteHalf) -> Result<()> {
+    match request {
+        Request::Send { server, command } => {
+            for line in interface::output(resolve(server)?, command.into_bytes()).await? {
+                write(writer, &Response::Line(line.as_ref().into())).await?;
+            }
+        }
+        Request::List { server } => {
+            let list = interface::list(resolve(server)?, interface::RESPONSE_TIMEOUT).await?;
+            let names = list
+                .players
+                .iter()
+                .map(|player| &*player.name)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let line = if names.is_empty() {
+                format!("There are 0/{} players online.\n", list.max)
+            } else {
+                format!(
+                    "There are {}/{} players online: {names}.\n",
+                    list.players.len(),
+                    list.max
+                )
+            };
+            write(writer, &Response::Line(line.into())).await?;
+        }
+        Request::Tail { server } => {
+            let mut lines = resolve(server)?.log_lines.subscribe();
+            loop {
+                match lines.recv().await {
+                    Ok(line) => {
+                        // The client going away is the normal way for a tail to end
+                        if write(writer, &Response::Line(line.as_ref().into()))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve(server: Option<String>) -> Result<&'static Instance> {
+    match server {
+        Some(name) => instance::get(&name).ok_or_else(|| eyre!("There is no server named {name}.")),
+        None => Ok(instance::default()),
+    }
+}
+
+async fn write(writer: &mut OwnedWriteHalf, response: &Response) -> Result<()> {
+    let mut buf = serde_json::to_vec(response)?;
+    buf.push(b'\n');
+    writer.write_all(&buf).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Result<Request>> {
+        Request::from_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn ignores_other_arguments() {
+        assert!(parse(&[]).is_none());
+        assert!(parse(&["java", "-jar", "server.jar", "nogui"]).is_none());
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        assert!(matches!(
+            parse(&["send", "say", "hello"]),
+            Some(Ok(Request::Send { server: None, command })) if command == "say hello"
+        ));
+        assert!(matches!(
+            parse(&["list"]),
+            Some(Ok(Request::List { server: None }))
+        ));
+        assert!(matches!(
+            parse(&["tail"]),
+            Some(Ok(Request::Tail { server: None }))
+        ));
+    }
+
+    #[test]
+    fn parses_the_server_flag() {
+        assert!(matches!(
+            parse(&["send", "--server", "survival", "list"]),
+            Some(Ok(Request::Send { server: Some(server), command }))
+                if server == "survival" && command == "list"
+        ));
+        assert!(matches!(
+            parse(&["tail", "-s", "creative"]),
+            Some(Ok(Request::Tail { server: Some(server) })) if server == "creative"
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_usage() {
+        assert!(matches!(parse(&["send"]), Some(Err(_))));
+        assert!(matches!(
+            parse(&["send", "--server", "survival"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(parse(&["list", "extra"]), Some(Err(_))));
+        assert!(matches!(parse(&["tail", "--server"]), Some(Err(_))));
+    }
+}
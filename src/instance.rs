@@ -0,0 +1,546 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock, atomic::AtomicBool},
+    time::{Duration, Instant},
+};
+
+use eyre::{bail, eyre};
+use parking_lot::Mutex;
+use tokio::sync::{broadcast, oneshot, watch};
+
+use crate::{
+    DeathMessage, Lang, Result, api,
+    logsource::LogSource,
+    outbox::Outbox,
+    parsing::Dialect,
+    patterns::{self, Pattern},
+    supervisor::{Request, State},
+};
+
+static INSTANCES: OnceLock<Box<[Instance]>> = OnceLock::new();
+
+/// How mcglue runs a server and talks to it.
+pub enum Mode {
+    /// mcglue starts the server itself and talks to it over stdin and stdout.
+    Spawn {
+        program: String,
+        args: Vec<String>,
+        /// Directory to run the command in, or mcglue's own if `None`.
+        working_directory: Option<Box<Path>>,
+    },
+    /// The server is started elsewhere, so mcglue sends commands over RCON and follows its log
+    /// file.
+    Rcon { address: String, password: String },
+}
+
+/// Channel that a kind of message is relayed to, on top of the console channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relay {
+    Chat,
+    Staff,
+    Off,
+}
+
+impl FromStr for Relay {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chat" => Ok(Relay::Chat),
+            "staff" => Ok(Relay::Staff),
+            "off" => Ok(Relay::Off),
+            _ => Err(eyre!(
+                "Unknown relay target {s:?}, expected chat, staff or off"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Relays {
+    /// Actions sent with `/me`.
+    pub emotes: Relay,
+    /// Messages broadcast with `/say` by players and command blocks.
+    pub say: Relay,
+    /// Messages sent with `/teammsg`.
+    pub team_chat: Relay,
+    /// Private messages, which are never relayed to the chat channel.
+    pub whispers: Relay,
+}
+
+/// A server supervised by mcglue, along with its configuration and the channels used to talk
+/// to it.
+pub struct Instance {
+    pub name: Box<str>,
+    pub mode: Mode,
+    pub server_directory: Box<Path>,
+    /// Where the server's log lines are read from.
+    pub log_source: LogSource,
+    /// Layout of the server's log lines, or `None` until it is detected from the first line
+    /// that matches one.
+    pub dialect: Mutex<Option<Dialect>>,
+    pub channel_id: u64,
+    pub console_channel_id: u64,
+    /// Messages for the chat channel.
+    pub webhook: Outbox,
+    /// Messages for the console channel.
+    pub console_webhook: Outbox,
+    /// Messages for the staff channel, if there is one.
+    pub staff_webhook: Option<Outbox>,
+    /// Moderation actions, if they are recorded in a channel.
+    pub audit_webhook: Option<Outbox>,
+    /// Where messages other than chat are relayed.
+    pub relays: Relays,
+    pub schedule_file: Option<String>,
+    /// Log lines relayed as custom events, checked in order.
+    pub patterns: Box<[Pattern]>,
+    /// Address and password to accept RCON connections for the server on.
+    pub rcon_listen: Option<(String, String)>,
+
+    pub commands: (flume::Sender<Box<[u8]>>, flume::Receiver<Box<[u8]>>),
+    pub requests: (flume::Sender<Request>, flume::Receiver<Request>),
+    /// Commands that should only be sent once the server has finished starting.
+    pub held_commands: (flume::Sender<Box<[u8]>>, flume::Receiver<Box<[u8]>>),
+    /// Lines relayed to the console channel.
+    pub log_lines: broadcast::Sender<Arc<str>>,
+    pub state: watch::Sender<State>,
+    /// Set when a `stop` command is written to the server, so that the following exit is not
+    /// mistaken for a crash.
+    pub stop_requested: AtomicBool,
+    /// Process ID of the running server and when it was spawned.
+    pub process: Mutex<Option<(u32, Instant)>>,
+    /// Version of the running server, as logged when it started.
+    pub version: Mutex<Option<Box<str>>>,
+    /// Death messages and advancements for the server's version, once they have been loaded.
+    pub lang: Mutex<Option<Lang>>,
+    /// Round-trip time of the last successful watchdog check, if the server has responded
+    /// since starting.
+    pub round_trip: Mutex<Option<Duration>>,
+    /// Callers waiting for the next thread dump the server prints.
+    pub thread_dumps: Mutex<Vec<oneshot::Sender<Arc<str>>>>,
+    /// Recently relayed events, oldest first.
+    pub events: Mutex<VecDeque<api::Event>>,
+}
+
+impl Instance {
+    fn from_env(name: &str, prefix: Option<&str>, mut mode: Mode) -> Result<Self> {
+        use crate::env;
+
+        let server_directory: Box<Path> = var(prefix, "SERVER_DIRECTORY")?
+            .or_else(env::server_directory)
+            .map(PathBuf::from)
+            .ok_or_else(|| missing(prefix, "SERVER_DIRECTORY"))?
+            .into();
+
+        // Servers configured through `SERVERS` are started from their own directories
+        if let (
+            Mode::Spawn {
+                working_directory, ..
+            },
+            Some(_),
+        ) = (&mut mode, prefix)
+        {
+            *working_directory = Some(server_directory.clone());
+        }
+
+        let webhook_url: String = var(prefix, "DISCORD_WEBHOOK_URL")?
+            .or_else(env::discord_webhook_url)
+            .ok_or_else(|| missing(prefix, "DISCORD_WEBHOOK_URL"))?;
+        let console_webhook_url: String = var(prefix, "DISCORD_CONSOLE_WEBHOOK_URL")?
+            .or_else(env::discord_console_webhook_url)
+            .ok_or_else(|| missing(prefix, "DISCORD_CONSOLE_WEBHOOK_URL"))?;
+
+        let token = env::discord_bot_token();
+        let state_directory =
+            PathBuf::from(env::state_directory().unwrap_or_else(|| String::from(".mcglue")));
+
+        let log_source = match var::<String>(prefix, "LOG_SOURCE")?.or_else(env::log_source) {
+            Some(source) => source.parse()?,
+            None => match mode {
+                Mode::Spawn { .. } => LogSource::Stdout,
+                Mode::Rcon { .. } => LogSource::File,
+            },
+        };
+
+        if let (Mode::Rcon { .. }, LogSource::Stdout) = (&mode, log_source) {
+            bail!("{name} is not started by mcglue, so its logs can only be read from a file");
+        }
+
+        let staff_webhook_url: Option<String> =
+            var(prefix, "DISCORD_STAFF_WEBHOOK_URL")?.or_else(env::discord_staff_webhook_url);
+
+        let audit_webhook_url: Option<String> =
+            var(prefix, "DISCORD_AUDIT_WEBHOOK_URL")?.or_else(env::discord_audit_webhook_url);
+
+        let staff = staff_webhook_url.is_some();
+        let relays = Relays {
+            emotes: relay(
+                prefix,
+                "RELAY_EMOTES",
+                env::relay_emotes,
+                Relay::Chat,
+                staff,
+            )?,
+            say: relay(prefix, "RELAY_SAY", env::relay_say, Relay::Chat, staff)?,
+            team_chat: relay(
+                prefix,
+                "RELAY_TEAM_CHAT",
+                env::relay_team_chat,
+                Relay::Staff,
+                staff,
+            )?,
+            whispers: relay(
+                prefix,
+                "RELAY_WHISPERS",
+                env::relay_whispers,
+                Relay::Staff,
+                staff,
+            )?,
+        };
+
+        if relays.whispers == Relay::Chat {
+            bail!("Whispers for {name} can only be relayed to the staff channel");
+        }
+
+        let dialect = match var::<String>(prefix, "LOG_DIALECT")?.or_else(env::log_dialect) {
+            Some(dialect) if dialect.eq_ignore_ascii_case("auto") => None,
+            Some(dialect) => Some(dialect.parse()?),
+            None => None,
+        };
+
+        Ok(Self {
+            name: name.into(),
+            mode,
+            server_directory,
+            log_source,
+            dialect: Mutex::new(dialect),
+            channel_id: var(prefix, "DISCORD_CHANNEL_ID")?
+                .or_else(env::discord_channel_id)
+                .ok_or_else(|| missing(prefix, "DISCORD_CHANNEL_ID"))?,
+            console_channel_id: var(prefix, "DISCORD_CONSOLE_CHANNEL_ID")?
+                .or_else(env::discord_console_channel_id)
+                .ok_or_else(|| missing(prefix, "DISCORD_CONSOLE_CHANNEL_ID"))?,
+            webhook: Outbox::new(
+                &token,
+                &webhook_url,
+                state_directory.join(format!("outbox-{name}.jsonl")),
+            )?,
+            console_webhook: Outbox::new(
+                &token,
+                &console_webhook_url,
+                state_directory.join(format!("outbox-{name}-console.jsonl")),
+            )?,
+            staff_webhook: staff_webhook_url
+                .map(|url| {
+                    Outbox::new(
+                        &token,
+                        &url,
+                        state_directory.join(format!("outbox-{name}-staff.jsonl")),
+                    )
+                })
+                .transpose()?,
+            audit_webhook: audit_webhook_url
+                .map(|url| {
+                    Outbox::new(
+                        &token,
+                        &url,
+                        state_directory.join(format!("outbox-{name}-audit.jsonl")),
+                    )
+                })
+                .transpose()?,
+            relays,
+            schedule_file: var(prefix, "SCHEDULE_FILE")?.or_else(env::schedule_file),
+            patterns: match var::<String>(prefix, "PATTERNS_FILE")?.or_else(env::patterns_file) {
+                Some(path) => patterns::load(Path::new(&path))?,
+                None => Box::new([]),
+            },
+            rcon_listen: rcon_listen(prefix)?,
+
+            commands: flume::unbounded(),
+            requests: flume::unbounded(),
+            held_commands: flume::unbounded(),
+            log_lines: broadcast::channel(256).0,
+            state: watch::Sender::new(State::Stopped),
+            stop_requested: AtomicBool::new(false),
+            process: Mutex::new(None),
+            version: Mutex::new(None),
+            lang: Mutex::new(None),
+            round_trip: Mutex::new(None),
+            thread_dumps: Mutex::new(Vec::new()),
+            events: Mutex::new(VecDeque::with_capacity(api::RECENT_EVENTS)),
+        })
+    }
+
+    pub async fn command(&self, s: impl Into<Box<[u8]>>) -> Result<()> {
+        self.commands.0.send_async(s.into()).await?;
+        Ok(())
+    }
+
+    pub fn command_sync(&self, s: impl Into<Box<[u8]>>) -> Result<()> {
+        self.commands.0.send(s.into())?;
+        Ok(())
+    }
+
+    /// Sends a command once the server has finished starting, or drops it if the server is not
+    /// running. Commands are sent in the order they were held.
+    pub async fn command_when_running(&self, s: impl Into<Box<[u8]>>) -> Result<()> {
+        self.held_commands.0.send_async(s.into()).await?;
+        Ok(())
+    }
+
+    pub async fn request(&self, request: Request) -> Result<()> {
+        self.requests.0.send_async(request).await?;
+        Ok(())
+    }
+
+    pub fn state(&self) -> State {
+        *self.state.borrow()
+    }
+
+    /// Waits for the server to finish starting, failing if it is not running.
+    pub async fn wait_until_running(&self) -> Result<()> {
+        let mut state = self.state.subscribe();
+        if *state.wait_for(|s| *s != State::Starting).await? != State::Running {
+            bail!("The server is not running.");
+        }
+
+        Ok(())
+    }
+
+    /// Layout of the server's log lines, assumed to be vanilla until detected.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect.lock().unwrap_or(Dialect::Vanilla)
+    }
+
+    /// Death messages the server may log, or none until they have been loaded.
+    pub fn death_messages(&self) -> &'static [DeathMessage] {
+        self.lang
+            .lock()
+            .as_ref()
+            .map_or(&[], |lang| lang.death_messages)
+    }
+
+    /// Every outbox messages for the server are queued in.
+    pub fn outboxes(&self) -> impl Iterator<Item = &Outbox> + Clone {
+        [&self.webhook, &self.console_webhook]
+            .into_iter()
+            .chain(&self.staff_webhook)
+            .chain(&self.audit_webhook)
+    }
+
+    /// Returns the outbox for the channel `relay` points to, if messages are relayed to one.
+    pub fn outbox(&self, relay: Relay) -> Option<&Outbox> {
+        match relay {
+            Relay::Chat => Some(&self.webhook),
+            Relay::Staff => self.staff_webhook.as_ref(),
+            Relay::Off => None,
+        }
+    }
+
+    /// The log file the server is currently writing to.
+    pub fn latest_log(&self) -> PathBuf {
+        self.server_directory.join("logs").join("latest.log")
+    }
+
+    /// Returns the process ID of the server and when it was spawned, if it is running.
+    pub fn process(&self) -> Option<(u32, Instant)> {
+        *self.process.lock()
+    }
+
+    /// Sends `signal` to the running server's JVM, failing if it is not running or no JVM can be
+    /// found.
+    #[cfg(unix)]
+    pub fn signal(&self, signal: libc::c_int) -> Result<()> {
+        let Some((pid, _)) = self.process() else {
+            bail!("The server is not running.");
+        };
+        let pid = crate::monitor::jvm_pid(pid)?;
+
+        crate::supervisor::send_signal(pid as libc::pid_t, signal)
+    }
+}
+
+/// Loads the servers listed in `SERVERS`, or a single server started with the command line
+/// mcglue was given.
+pub fn load() -> Result<()> {
+    let instances = match crate::env::servers() {
+        None => {
+            let mode = match crate::env::rcon_address() {
+                Some(address) => Mode::Rcon {
+                    address,
+                    password: rcon_password(None)?,
+                },
+                None => {
+                    let mut args = std::env::args();
+                    let binary_name = args.next().unwrap_or_else(|| String::from("gluemc"));
+
+                    let Some(program) = args.next() else {
+                        println!("Usage: {binary_name} <command>");
+                        std::process::exit(1);
+                    };
+
+                    Mode::Spawn {
+                        program,
+                        args: args.collect(),
+                        working_directory: None,
+                    }
+                }
+            };
+
+            vec![Instance::from_env("server", None, mode)?]
+        }
+        Some(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let prefix = name.to_ascii_uppercase().replace('-', "_");
+
+                // Each server has its own RCON address, so there is no fallback for it
+                if let Some(address) = var(Some(&prefix), "RCON_ADDRESS")? {
+                    let password = rcon_password(Some(&prefix))?;
+                    return Instance::from_env(
+                        name,
+                        Some(&prefix),
+                        Mode::Rcon { address, password },
+                    );
+                }
+
+                let command = var::<String>(Some(&prefix), "SERVER_COMMAND")?
+                    .ok_or_else(|| missing(Some(&prefix), "SERVER_COMMAND"))?;
+
+                let mut command = command.split_whitespace().map(String::from);
+                let Some(program) = command.next() else {
+                    bail!("{prefix}_SERVER_COMMAND should not be empty");
+                };
+
+                let mode = Mode::Spawn {
+                    program,
+                    args: command.collect(),
+                    working_directory: None,
+                };
+                Instance::from_env(name, Some(&prefix), mode)
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    if instances.is_empty() {
+        bail!("SERVERS should be set to a comma-separated list of server names");
+    }
+
+    // Discord messages are routed to a server by their channel, so no two servers may share one
+    for (i, first) in instances.iter().enumerate() {
+        for second in &instances[i + 1..] {
+            let first_channels = [first.channel_id, first.console_channel_id];
+            let second_channels = [second.channel_id, second.console_channel_id];
+            if first_channels.iter().any(|id| second_channels.contains(id)) {
+                bail!(
+                    "Servers {} and {} use the same Discord channel; set <NAME>_DISCORD_CHANNEL_ID and <NAME>_DISCORD_CONSOLE_CHANNEL_ID for each server",
+                    first.name,
+                    second.name
+                );
+            }
+        }
+    }
+
+    INSTANCES
+        .set(instances.into())
+        .map_err(|_| eyre!("Servers were already loaded"))?;
+
+    Ok(())
+}
+
+pub fn all() -> &'static [Instance] {
+    INSTANCES.get().unwrap()
+}
+
+/// The server console input and unqualified commands go to.
+pub fn default() -> &'static Instance {
+    &all()[0]
+}
+
+pub fn get(name: &str) -> Option<&'static Instance> {
+    all().iter().find(|instance| &*instance.name == name)
+}
+
+/// Finds the server whose chat or console channel has the given ID.
+pub fn by_channel(channel_id: u64) -> Option<&'static Instance> {
+    all().iter().find(|instance| {
+        instance.channel_id == channel_id || instance.console_channel_id == channel_id
+    })
+}
+
+/// Reads `<PREFIX>_<key>`, if there is a prefix.
+fn var<T>(prefix: Option<&str>, key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let Some(prefix) = prefix else {
+        return Ok(None);
+    };
+
+    match std::env::var(format!("{prefix}_{key}")) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| eyre!("Could not parse {prefix}_{key}: {e}")),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(eyre!("Could not read {prefix}_{key}: {e}")),
+    }
+}
+
+fn rcon_password(prefix: Option<&str>) -> Result<String> {
+    var(prefix, "RCON_PASSWORD")?
+        .or_else(crate::env::rcon_password)
+        .ok_or_else(|| missing(prefix, "RCON_PASSWORD"))
+}
+
+fn rcon_listen(prefix: Option<&str>) -> Result<Option<(String, String)>> {
+    // Each server needs its own port, so there is no fallback for the address
+    let address = match prefix {
+        Some(_) => var(prefix, "RCON_LISTEN_ADDRESS")?,
+        None => crate::env::rcon_listen_address(),
+    };
+
+    let Some(address) = address else {
+        return Ok(None);
+    };
+
+    let password = var(prefix, "RCON_LISTEN_PASSWORD")?
+        .or_else(crate::env::rcon_listen_password)
+        .ok_or_else(|| missing(prefix, "RCON_LISTEN_PASSWORD"))?;
+
+    Ok(Some((address, password)))
+}
+
+/// Reads where a kind of message is relayed. Messages are not relayed to the staff channel by
+/// default if there is none, but setting it explicitly requires one.
+fn relay(
+    prefix: Option<&str>,
+    key: &str,
+    fallback: fn() -> Option<String>,
+    default: Relay,
+    staff: bool,
+) -> Result<Relay> {
+    let Some(relay) = var::<String>(prefix, key)?.or_else(fallback) else {
+        return Ok(default);
+    };
+
+    let relay = relay.parse()?;
+    if relay == Relay::Staff && !staff {
+        bail!("{key} is set to staff, but there is no DISCORD_STAFF_WEBHOOK_URL");
+    }
+
+    Ok(relay)
+}
+
+fn missing(prefix: Option<&str>, key: &str) -> eyre::Report {
+    match prefix {
+        Some(prefix) => eyre!("{prefix}_{key} or {key} should be set"),
+        None => eyre!("{key} should be set"),
+    }
+}
@@ -1,11 +1,113 @@
-use std::sync::OnceLock;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{Result, parsing::ListData};
+use eyre::bail;
+use tokio::sync::broadcast::error::RecvError;
 
-pub static LIST_SENDER: OnceLock<tokio::sync::broadcast::Sender<ListData>> = OnceLock::new();
+use crate::{
+    Result,
+    instance::Instance,
+    parsing::{ListData, ListUuidsLog, Log, OwnedPlayerData},
+};
 
-pub async fn list() -> Result<ListData> {
-    let mut rx = LIST_SENDER.get().unwrap().subscribe();
-    crate::command(*b"list uuids").await?;
-    Ok(rx.recv().await?)
+/// How long to wait for a response to a command by default.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for another log line before considering a command's output complete.
+const QUIET_PERIOD: Duration = Duration::from_millis(250);
+
+/// Longest time to collect a command's output for.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sends `command` to the server and returns the first value `matcher` produces from the log
+/// lines that follow, failing if there is none within `timeout`.
+pub async fn request<T>(
+    server: &Instance,
+    command: impl Into<Box<[u8]>>,
+    timeout: Duration,
+    mut matcher: impl FnMut(&[u8]) -> Option<T>,
+) -> Result<T> {
+    let command = command.into();
+
+    // Subscribe first so the response cannot be missed
+    let mut lines = server.log_lines.subscribe();
+    server.command(command.clone()).await?;
+
+    let response = tokio::time::timeout(timeout, async {
+        loop {
+            match lines.recv().await {
+                Ok(line) => {
+                    let line = line.strip_suffix('\n').unwrap_or(&line);
+                    if let Some(value) = matcher(line.as_bytes()) {
+                        return Ok(value);
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => bail!("The server stopped before responding."),
+            }
+        }
+    })
+    .await;
+
+    match response {
+        Ok(response) => response,
+        Err(_) => bail!(
+            "The server did not respond to `{}` within {}s.",
+            String::from_utf8_lossy(&command),
+            timeout.as_secs()
+        ),
+    }
+}
+
+/// Sends `command` to the server and returns the log lines that follow it until the server goes
+/// quiet. Commands do not mark the end of their output, so lines logged at the same time for
+/// other reasons are included.
+pub async fn output(server: &Instance, command: impl Into<Box<[u8]>>) -> Result<Vec<Arc<str>>> {
+    let mut lines = server.log_lines.subscribe();
+    server.command(command).await?;
+
+    let mut output = Vec::new();
+    let deadline = Instant::now() + OUTPUT_TIMEOUT;
+    while Instant::now() < deadline {
+        match tokio::time::timeout(QUIET_PERIOD, lines.recv()).await {
+            Ok(Ok(line)) => output.push(line),
+            Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        }
+    }
+
+    Ok(output)
+}
+
+/// Like [`request`], but with `matcher` given each line parsed as a [`Log`].
+pub async fn request_log<T>(
+    server: &Instance,
+    command: impl Into<Box<[u8]>>,
+    timeout: Duration,
+    mut matcher: impl FnMut(Log<'_>) -> Option<T>,
+) -> Result<T> {
+    request(server, command, timeout, |line| {
+        let (log, _) = Log::parse(line, server.dialect(), server.death_messages()).ok()?;
+        matcher(log)
+    })
+    .await
+}
+
+/// Lists the players online, failing if the server does not respond within `timeout`.
+pub async fn list(server: &Instance, timeout: Duration) -> Result<ListData> {
+    server.wait_until_running().await?;
+
+    request_log(server, *b"list uuids", timeout, |log| match log {
+        Log::List(ListUuidsLog { players, max }) => Some(
+            players
+                .iter()
+                .map(OwnedPlayerData::try_from)
+                .collect::<Result<Arc<[_]>>>()
+                .map(|players| ListData { players, max }),
+        ),
+        _ => None,
+    })
+    .await?
 }
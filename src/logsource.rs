@@ -0,0 +1,239 @@
+use std::{fs::Metadata, io::SeekFrom, path::PathBuf, str::FromStr, time::Duration};
+
+use eyre::bail;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
+};
+
+use crate::{Error, Result, Stream};
+
+/// How often to check the log file for new lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where the lines relayed to Discord are read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogSource {
+    /// The stdout and stderr of the spawned server.
+    Stdout,
+    /// `logs/latest.log` in the server directory, following it as the server writes to it.
+    File,
+}
+
+impl FromStr for LogSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stdout" => Ok(LogSource::Stdout),
+            "file" => Ok(LogSource::File),
+            _ => bail!(r#"Unknown log source {s:?}, expected "stdout" or "file""#),
+        }
+    }
+}
+
+/// Follows a log file, sending every line appended to it. The file is followed across
+/// truncation and across rotation, where it is renamed or compressed away and a new file is
+/// created in its place.
+pub async fn follow(path: PathBuf, lines: flume::Sender<(Stream, Vec<u8>)>) -> Result<()> {
+    // Only lines written from now on are relayed, unless the file does not exist yet
+    let mut from = SeekFrom::End(0);
+    let (mut reader, mut id) = loop {
+        match open(&path, from).await {
+            Ok(opened) => break opened,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                from = SeekFrom::Start(0);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let mut position = reader.stream_position().await?;
+    let mut buf = Vec::with_capacity(256);
+
+    loop {
+        let read = reader.read_until(b'\n', &mut buf).await?;
+        position += read as u64;
+
+        if read > 0 && buf.ends_with(b"\n") {
+            lines
+                .send_async((Stream::Stdout, std::mem::take(&mut buf)))
+                .await?;
+            continue;
+        }
+
+        // At the end of the file, so check whether it is still the one being written to
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if file_id(&metadata) != id => {
+                // Lines may have been written after reaching the end and before the rotation
+                while reader.read_until(b'\n', &mut buf).await? > 0 {
+                    if buf.ends_with(b"\n") {
+                        lines
+                            .send_async((Stream::Stdout, std::mem::take(&mut buf)))
+                            .await?;
+                    }
+                }
+
+                // Whatever is left is the old file's last line, which was never finished
+                if !buf.is_empty() {
+                    buf.push(b'\n');
+                    lines
+                        .send_async((Stream::Stdout, std::mem::take(&mut buf)))
+                        .await?;
+                }
+
+                (reader, id) = open(&path, SeekFrom::Start(0)).await?;
+                position = 0;
+            }
+            Ok(metadata) if metadata.len() < position => {
+                reader.seek(SeekFrom::Start(0)).await?;
+                position = 0;
+                buf.clear();
+            }
+            // The new file may not have been created yet while rotating
+            _ => tokio::time::sleep(POLL_INTERVAL).await,
+        }
+    }
+}
+
+async fn open(path: &PathBuf, from: SeekFrom) -> std::io::Result<(BufReader<File>, u64)> {
+    let mut file = File::open(path).await?;
+    let id = file_id(&file.metadata().await?);
+    file.seek(from).await?;
+
+    Ok((BufReader::new(file), id))
+}
+
+/// Identifies a file independently of its path, to notice when a path refers to a new file.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn file_id(metadata: &Metadata) -> u64 {
+    metadata
+        .created()
+        .ok()
+        .and_then(|created| created.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |created| created.as_nanos() as u64)
+}
+
+/// Returns the first line of the log file that `matches`, such as the line a server logs its
+/// version on.
+pub async fn find(
+    path: PathBuf,
+    mut matches: impl FnMut(&[u8]) -> bool,
+) -> Result<Option<Vec<u8>>> {
+    let mut reader = BufReader::new(File::open(&path).await?);
+    let mut buf = Vec::with_capacity(256);
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf).await? == 0 {
+            return Ok(None);
+        }
+
+        if matches(&buf) {
+            return Ok(Some(buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// Returns the path of a log file in a directory of its own, which does not exist yet.
+    fn log_file(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("mcglue-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.join("latest.log")
+    }
+
+    fn append(path: &PathBuf, text: &str) {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap();
+    }
+
+    async fn next(lines: &flume::Receiver<(Stream, Vec<u8>)>) -> String {
+        let (_, line) = tokio::time::timeout(Duration::from_secs(5), lines.recv_async())
+            .await
+            .expect("no line was read")
+            .unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn follows_appended_and_partial_lines() {
+        let path = log_file("append");
+        let (tx, rx) = flume::unbounded();
+        let follower = tokio::spawn(follow(path.clone(), tx));
+
+        append(&path, "first\n");
+        assert_eq!(next(&rx).await, "first\n");
+
+        // A line is only sent once it is finished
+        append(&path, "sec");
+        tokio::time::sleep(POLL_INTERVAL * 2).await;
+        assert!(rx.is_empty());
+        append(&path, "ond\n");
+        assert_eq!(next(&rx).await, "second\n");
+
+        follower.abort();
+    }
+
+    #[tokio::test]
+    async fn follows_rotation() {
+        let path = log_file("rotate");
+        let rotated = path.with_file_name("1.log");
+        let (tx, rx) = flume::unbounded();
+        let follower = tokio::spawn(follow(path.clone(), tx));
+
+        append(&path, "one\n");
+        assert_eq!(next(&rx).await, "one\n");
+
+        // Written just before the rename, so it is only in the old file
+        append(&path, "two\n");
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&path, "three\n");
+        assert_eq!(next(&rx).await, "two\n");
+        assert_eq!(next(&rx).await, "three\n");
+
+        // An unfinished last line is still sent once the file is rotated away
+        append(&path, "four");
+        tokio::time::sleep(POLL_INTERVAL * 2).await;
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&path, "five\n");
+        assert_eq!(next(&rx).await, "four\n");
+        assert_eq!(next(&rx).await, "five\n");
+
+        follower.abort();
+    }
+
+    #[tokio::test]
+    async fn follows_truncation() {
+        let path = log_file("truncate");
+        let (tx, rx) = flume::unbounded();
+        let follower = tokio::spawn(follow(path.clone(), tx));
+
+        append(&path, "a line longer than the next\n");
+        assert_eq!(next(&rx).await, "a line longer than the next\n");
+
+        std::fs::write(&path, "short\n").unwrap();
+        assert_eq!(next(&rx).await, "short\n");
+
+        follower.abort();
+    }
+}
@@ -1,52 +1,80 @@
+mod api;
 mod bot;
+#[cfg(unix)]
+mod control;
+mod instance;
 mod interface;
 mod jar;
+mod logsource;
+mod monitor;
+mod outbox;
 mod parsing;
-
-use crate::parsing::*;
+mod patterns;
+mod rcon;
+mod scheduler;
+mod shutdown;
+mod supervisor;
+mod threaddump;
+mod watchdog;
+
+use crate::{instance::Instance, outbox::Outbox, parsing::*};
 use async_signal::{Signal, Signals};
-use eyre::{bail, eyre};
+use eyre::bail;
 use rustyline::error::ReadlineError;
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fs::OpenOptions,
     io::Read,
     path::{Path, PathBuf},
-    process::Stdio,
-    sync::{Arc, OnceLock},
+    sync::Arc,
     time::Duration,
 };
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    sync::watch,
+    task::JoinHandle,
+};
 use zip::ZipArchive;
 
 use bstr::ByteSlice;
 use chumsky::prelude::*;
+use parking_lot::Mutex;
 use poise::serenity_prelude::{
-    CreateEmbed, CreateEmbedAuthor, ExecuteWebhook, Http, Webhook, colours, futures::StreamExt,
+    Colour, CreateEmbed, CreateEmbedAuthor, EditWebhookMessage, ExecuteWebhook, MessageId, colours,
+    futures::{self, StreamExt},
 };
 
 type Error = eyre::Error;
 type Result<T, E = Error> = eyre::Result<T, E>;
 
-static LANG: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
-
-#[allow(clippy::type_complexity)]
-static DEATH_MESSAGES: OnceLock<
-    &'static [(
-        &'static [u8],
-        DeathMessageComponent,
-        &'static [u8],
-        DeathMessageComponent,
-        &'static [u8],
-        DeathMessageComponent,
-        &'static [u8],
-    )],
-> = OnceLock::new();
-
-static ADVANCEMENTS: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
-
-static COMMAND_CHANNEL: OnceLock<flume::Sender<Box<[u8]>>> = OnceLock::new();
+/// How long to wait for more lines of an entry, such as the rest of a stack trace, before
+/// relaying it.
+const ENTRY_QUIET_PERIOD: Duration = Duration::from_millis(50);
+
+/// How long to wait when exiting for queued messages to be delivered to Discord.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A death message from the language files, as the text around each of its placeholders.
+type DeathMessage = (
+    &'static [u8],
+    DeathMessageComponent,
+    &'static [u8],
+    DeathMessageComponent,
+    &'static [u8],
+    DeathMessageComponent,
+    &'static [u8],
+);
+
+/// Death messages and advancements for the version of the game a server runs, including those
+/// added by its mods.
+struct Lang {
+    /// Version the tables were loaded for, so that they are only loaded again after an update.
+    version: Box<str>,
+    death_messages: &'static [DeathMessage],
+    /// Description of each advancement, by title.
+    advancements: HashMap<&'static str, &'static str>,
+}
 
 #[derive(Clone, Copy, Debug)]
 enum DeathMessageComponent {
@@ -64,31 +92,129 @@ mod env {
         discord_bot_token, "DISCORD_BOT_TOKEN", String,
         "DISCORD_BOT_TOKEN should be set to a Discord bot token";
 
-        discord_webhook_url, "DISCORD_WEBHOOK_URL", String,
-        "DISCORD_WEBHOOK_URL should be set to a Discord webhook URL";
+        discord_webhook_url?, "DISCORD_WEBHOOK_URL", String,
+        "DISCORD_WEBHOOK_URL should be set to a Discord webhook URL, unless set for each server";
+
+        discord_console_webhook_url?, "DISCORD_CONSOLE_WEBHOOK_URL", String,
+        "DISCORD_CONSOLE_WEBHOOK_URL should be set to a Discord webhook URL, unless set for each server";
+
+        discord_staff_webhook_url?, "DISCORD_STAFF_WEBHOOK_URL", String,
+        "DISCORD_STAFF_WEBHOOK_URL should be set to a Discord webhook URL for a channel only staff can read";
 
-        discord_console_webhook_url, "DISCORD_CONSOLE_WEBHOOK_URL", String,
-        "DISCORD_CONSOLE_WEBHOOK_URL should be set to a Discord webhook URL";
+        discord_audit_webhook_url?, "DISCORD_AUDIT_WEBHOOK_URL", String,
+        "DISCORD_AUDIT_WEBHOOK_URL should be set to a Discord webhook URL for a channel that records kicks, bans, ops and whitelist changes";
 
-        discord_channel_id, "DISCORD_CHANNEL_ID", u64,
-        "DISCORD_CHANNEL_ID should be set to a Discord channel ID";
+        discord_channel_id?, "DISCORD_CHANNEL_ID", u64,
+        "DISCORD_CHANNEL_ID should be set to a Discord channel ID, unless set for each server";
 
-        discord_console_channel_id, "DISCORD_CONSOLE_CHANNEL_ID", u64,
-        "DISCORD_CONSOLE_CHANNEL_ID should be set to a Discord channel ID";
+        discord_console_channel_id?, "DISCORD_CONSOLE_CHANNEL_ID", u64,
+        "DISCORD_CONSOLE_CHANNEL_ID should be set to a Discord channel ID, unless set for each server";
 
         discord_operator_role_id, "DISCORD_OPERATOR_ROLE_ID", u64,
         "DISCORD_OPERATOR_ROLE_ID should be set to a Discord role ID";
 
-        server_directory, "SERVER_DIRECTORY", String,
-        "SERVER_DIRECTORY should be set to the path to the server's root directory";
+        server_directory?, "SERVER_DIRECTORY", String,
+        "SERVER_DIRECTORY should be set to the path to the server's root directory, unless set for each server";
+
+        servers?, "SERVERS", String,
+        "SERVERS should be set to a comma-separated list of server names to run several servers, each configured with variables prefixed by its upper-cased name";
+
+        rcon_address?, "RCON_ADDRESS", String,
+        "RCON_ADDRESS should be set to the host:port of the server's RCON to connect to a server mcglue does not start";
+
+        rcon_password?, "RCON_PASSWORD", String,
+        "RCON_PASSWORD should be set to the server's RCON password when RCON_ADDRESS is set";
+
+        rcon_listen_address?, "RCON_LISTEN_ADDRESS", String,
+        "RCON_LISTEN_ADDRESS should be set to the host:port mcglue should accept RCON connections on, such as 127.0.0.1:25576";
+
+        rcon_listen_password?, "RCON_LISTEN_PASSWORD", String,
+        "RCON_LISTEN_PASSWORD should be set to the password for connections to RCON_LISTEN_ADDRESS";
+
+        api_address?, "API_ADDRESS", String,
+        "API_ADDRESS should be set to the host:port to serve the HTTP API on, such as 127.0.0.1:8080";
+
+        api_token?, "API_TOKEN", String,
+        "API_TOKEN should be set to the bearer token required to send commands through the HTTP API";
+
+        state_directory?, "STATE_DIRECTORY", String,
+        r#"STATE_DIRECTORY (".mcglue" by default) should be set to the directory mcglue keeps messages it could not deliver to Discord in"#;
+
+        log_source?, "LOG_SOURCE", String,
+        r#"LOG_SOURCE ("stdout" by default, or "file" with RCON_ADDRESS) should be set to "file" to read logs from logs/latest.log instead of the server's output"#;
+
+        log_dialect?, "LOG_DIALECT", String,
+        r#"LOG_DIALECT ("auto" by default) should be set to the layout of the server's log lines: "vanilla", "paper", "spigot", "forge", "neoforge" or "fabric""#;
 
         language?, "GAME_LANGUAGE", String,
         r#"GAME_LANGUAGE ("en_us" by default) should be set to the language the server is running"#;
-    }
-}
 
-pub fn server_directory() -> PathBuf {
-    PathBuf::from(crate::env::server_directory())
+        auto_restart?, "AUTO_RESTART", bool,
+        "AUTO_RESTART (false by default) should be set to whether to restart the server when it crashes";
+
+        restart_backoff?, "RESTART_BACKOFF", u64,
+        "RESTART_BACKOFF (5 by default) should be set to the initial number of seconds to wait before restarting a crashed server";
+
+        restart_backoff_max?, "RESTART_BACKOFF_MAX", u64,
+        "RESTART_BACKOFF_MAX (300 by default) should be set to the maximum number of seconds to wait before restarting a crashed server";
+
+        restart_max_crashes?, "RESTART_MAX_CRASHES", usize,
+        "RESTART_MAX_CRASHES (5 by default) should be set to the number of crashes within RESTART_CRASH_WINDOW after which to stop restarting";
+
+        restart_crash_window?, "RESTART_CRASH_WINDOW", u64,
+        "RESTART_CRASH_WINDOW (600 by default) should be set to the number of seconds crashes are counted over";
+
+        shutdown_warnings?, "SHUTDOWN_WARNINGS", String,
+        r#"SHUTDOWN_WARNINGS (empty by default) should be set to a comma-separated list of seconds before stopping the server at which to warn players, such as "60,30,10""#;
+
+        shutdown_stop_timeout?, "SHUTDOWN_STOP_TIMEOUT", u64,
+        "SHUTDOWN_STOP_TIMEOUT (60 by default) should be set to the number of seconds to wait after `stop` before terminating the server";
+
+        shutdown_term_timeout?, "SHUTDOWN_TERM_TIMEOUT", u64,
+        "SHUTDOWN_TERM_TIMEOUT (10 by default) should be set to the number of seconds to wait after terminating the server before killing it";
+
+        schedule_file?, "SCHEDULE_FILE", String,
+        "SCHEDULE_FILE should be set to the path to a crontab-style file of commands to run on a schedule";
+
+        relay_emotes?, "RELAY_EMOTES", String,
+        r#"RELAY_EMOTES ("chat" by default) should be set to "chat", "staff" or "off" to choose where /me actions are relayed"#;
+
+        relay_say?, "RELAY_SAY", String,
+        r#"RELAY_SAY ("chat" by default) should be set to "chat", "staff" or "off" to choose where /say messages from players and command blocks are relayed"#;
+
+        relay_team_chat?, "RELAY_TEAM_CHAT", String,
+        r#"RELAY_TEAM_CHAT ("staff" by default) should be set to "chat", "staff" or "off" to choose where team messages are relayed"#;
+
+        relay_whispers?, "RELAY_WHISPERS", String,
+        r#"RELAY_WHISPERS ("staff" by default) should be set to "staff" or "off" to choose whether private messages are relayed"#;
+
+        patterns_file?, "PATTERNS_FILE", String,
+        "PATTERNS_FILE should be set to the path to a JSON file of log lines to relay as custom events";
+
+        watchdog_interval?, "WATCHDOG_INTERVAL", u64,
+        "WATCHDOG_INTERVAL should be set to the number of seconds between checks that the server is responding";
+
+        watchdog_timeout?, "WATCHDOG_TIMEOUT", u64,
+        "WATCHDOG_TIMEOUT (10 by default) should be set to the number of seconds to wait for the server to respond to a check";
+
+        watchdog_failures?, "WATCHDOG_FAILURES", u32,
+        "WATCHDOG_FAILURES (3 by default) should be set to the number of consecutive failed checks after which the server is considered hung";
+
+        watchdog_restart?, "WATCHDOG_RESTART", bool,
+        "WATCHDOG_RESTART (false by default) should be set to whether to restart the server when it is hung";
+
+        alert_interval?, "ALERT_INTERVAL", u64,
+        "ALERT_INTERVAL (60 by default) should be set to the number of seconds between checks of the server's resource usage";
+
+        alert_rss_percent?, "ALERT_RSS_PERCENT", f64,
+        "ALERT_RSS_PERCENT should be set to the percentage of -Xmx the server's resident memory must reach to post an alert";
+
+        alert_cpu_percent?, "ALERT_CPU_PERCENT", f64,
+        "ALERT_CPU_PERCENT should be set to the percentage of a CPU core the server's usage must reach to post an alert";
+
+        control_socket?, "CONTROL_SOCKET", String,
+        r#"CONTROL_SOCKET ("mcglue.sock" by default) should be set to the path of the Unix socket used by `mcglue send`, `mcglue list` and `mcglue tail`"#;
+    }
 }
 
 pub fn language() -> String {
@@ -102,6 +228,14 @@ async fn main() -> Result<()> {
 
     let _ = dotenvy::dotenv();
 
+    #[cfg(unix)]
+    {
+        let args = std::env::args().skip(1).collect::<Vec<_>>();
+        if let Some(request) = control::Request::from_args(&args) {
+            return control::client(request?).await;
+        }
+    }
+
     if env::any_set() {
         env::assert_env_vars();
     } else {
@@ -109,613 +243,1039 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    let (signal_fin_tx, signal_fin_rx) = tokio::sync::oneshot::channel::<()>();
-    let mut signals = Signals::new([Signal::Term, Signal::Quit, Signal::Int])?;
-    tokio::task::spawn(async move {
-        signals.next().await;
-        eprintln!("Received exit signal");
-        let _ = signal_fin_tx.send(());
-    });
-
-    let token = env::discord_bot_token();
+    instance::load()?;
 
-    let http = Http::new(&token);
-    let webhook = Webhook::from_url(&http, &crate::env::discord_webhook_url()).await?;
+    let (exit_tx, exit_rx) = tokio::sync::watch::channel(false);
+    let exit_tx = Arc::new(exit_tx);
+    let mut signals = Signals::new([Signal::Term, Signal::Quit, Signal::Int])?;
+    {
+        let exit_tx = exit_tx.clone();
+        tokio::task::spawn(async move {
+            signals.next().await;
+            eprintln!("Received exit signal");
+            exit_tx.send_replace(true);
+        });
+    }
 
     let mut join_set = tokio::task::JoinSet::<Result<()>>::new();
 
-    let (logger, log_to_console) = {
-        let (tx, rx) = flume::unbounded::<Box<str>>();
-        let http = Http::new(&token);
-        let console_webhook =
-            Webhook::from_url(&http, &crate::env::discord_console_webhook_url()).await?;
-
-        let logger = tokio::task::spawn(async move {
-            let mut buf = String::with_capacity(4096);
-
-            while rx.sender_count() > 0 || !rx.is_empty() {
-                if !buf.is_empty() {
-                    let s = buf.chars().collect::<Vec<_>>();
-                    let mut s = s.as_slice();
-
-                    while s.len() > 2000 {
-                        if let Some(idx) = s
-                            .iter()
-                            .enumerate()
-                            .rev()
-                            .skip(s.len() - 2000)
-                            .find(|(_, c)| **c == '\n')
-                            .map(|(idx, _)| idx)
+    // The servers do not wait for Discord, so that they still start while it is unreachable
+    eprintln!("Starting Discord bot");
+    join_set.spawn(bot::run());
+
+    {
+        let exit_tx = exit_tx.clone();
+        std::thread::spawn(move || {
+            let mut editor = rustyline::DefaultEditor::new().unwrap();
+
+            loop {
+                match editor.readline("") {
+                    Ok(line) => {
+                        let _ = editor.add_history_entry(line.as_str());
+
+                        // `@<server> <command>` sends a command to a server other than the first
+                        let (server, command) = match line
+                            .strip_prefix('@')
+                            .and_then(|line| line.split_once(' '))
+                            .and_then(|(name, command)| Some((instance::get(name)?, command)))
                         {
-                            if console_webhook
-                                .execute(
-                                    &http,
-                                    false,
-                                    ExecuteWebhook::new()
-                                        .username("Console")
-                                        .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
-                                        .content(s[..idx].iter().collect::<String>()),
-                                )
-                                .await
-                                .is_err()
-                            {
-                                break;
-                            }
-
-                            s = &s[idx..];
-                        } else {
-                            if console_webhook
-                                .execute(
-                                    &http,
-                                    false,
-                                    ExecuteWebhook::new()
-                                        .username("Console")
-                                        .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
-                                        .content(s[..2000].iter().collect::<String>()),
-                                )
-                                .await
-                                .is_err()
-                            {
-                                break;
-                            }
+                            Some(routed) => routed,
+                            None => (instance::default(), line.as_str()),
+                        };
 
-                            s = &s[2000..];
+                        if let Err(e) = server.command_sync(command.as_bytes()) {
+                            eprintln!("Error sending command: {e:?}");
                         }
                     }
+                    Err(ReadlineError::Interrupted) => {
+                        eprintln!("Received CTRL+C, exiting.");
+                        break;
+                    }
+                    Err(ReadlineError::Eof) => {
+                        eprintln!("Received CTRL+D, exiting.");
+                        break;
+                    }
+                    Err(e) => eprintln!("Error reading line: {e:?}"),
+                }
+            }
 
-                    let _ = console_webhook
-                        .execute(
-                            &http,
-                            true,
-                            ExecuteWebhook::new()
-                                .username("Console")
-                                .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
-                                .content(s.iter().collect::<String>()),
-                        )
-                        .await;
+            exit_tx.send_replace(true);
+        });
+    }
 
-                    buf.clear();
-                }
+    #[cfg(unix)]
+    join_set.spawn(control::listen(control::socket_path()));
 
-                if let Ok(msg) = rx.recv_async().await {
-                    buf.push_str(&msg);
-                }
+    if let Some(address) = env::api_address() {
+        let address = address
+            .parse()
+            .map_err(|e| eyre::eyre!("Could not parse API_ADDRESS: {e}"))?;
+        join_set.spawn(api::serve(address, env::api_token()));
+    }
 
-                while let Ok(msg) = rx.try_recv() {
-                    buf.push_str(&msg);
-                }
+    let mut loggers = Vec::new();
+    let mut supervisors = tokio::task::JoinSet::<Result<()>>::new();
+    let mut deliveries = tokio::task::JoinSet::new();
+
+    for server in instance::all() {
+        for outbox in server.outboxes() {
+            deliveries.spawn(outbox.clone().deliver());
+            deliveries.spawn(outbox.clone().persist());
+        }
+
+        let (logger, log_to_console) = console_logger(server.console_webhook.clone());
+        loggers.push(logger);
+
+        join_set.spawn(supervisor::forward_held_commands(server));
+
+        if let Some(path) = &server.schedule_file {
+            let entries = scheduler::load(Path::new(path))?;
+            eprintln!(
+                "Loaded {} scheduled entries for {}",
+                entries.len(),
+                server.name
+            );
+            join_set.spawn(scheduler::run(server, entries, log_to_console.clone()));
+        }
+
+        if let Some((address, password)) = &server.rcon_listen {
+            join_set.spawn(rcon::listen(server, address.clone(), password.clone()));
+        }
+
+        if let Some(policy) = watchdog::WatchdogPolicy::from_env() {
+            join_set.spawn(watchdog::run(server, policy, log_to_console.clone()));
+        }
+
+        if let Some(policy) = monitor::AlertPolicy::from_env() {
+            join_set.spawn(monitor::run(server, policy, log_to_console.clone()));
+        }
+
+        supervisors.spawn(
+            supervisor::Supervisor {
+                server,
+                log_to_console,
             }
-        });
+            .run(exit_rx.clone()),
+        );
+    }
 
-        (logger, tx)
-    };
+    let mut result = Ok(());
+    while let Some(joined) = supervisors.join_next().await {
+        if let Err(e) = joined.map_err(Error::from).and_then(|r| r) {
+            // Stop the other servers cleanly rather than leaving them orphaned
+            eprintln!("Error while supervising server: {e:?}");
+            exit_tx.send_replace(true);
+            result = Err(e);
+        }
+    }
 
-    interface::LIST_SENDER
-        .set(tokio::sync::broadcast::channel(16).0)
-        .map_err(|e| eyre!("Could not set LIST_SENDER to a broadcast channel sender: {e:?}"))?;
+    eprintln!("Stopping wrapper");
 
-    let mut args = std::env::args();
-    let binary_name = args.next().unwrap_or_else(|| String::from("gluemc"));
+    join_set.shutdown().await;
+    for logger in loggers {
+        logger.await?;
+    }
 
-    eprintln!("Starting Discord bot");
-    let (bot_started_tx, bot_started_rx) = tokio::sync::oneshot::channel::<()>();
-    join_set.spawn(async move { bot::start_bot(bot_started_tx).await });
-    bot_started_rx.await?;
-
-    eprintln!("Starting server");
-    webhook
-        .execute(
-            &http,
-            false,
-            ExecuteWebhook::new()
-                .username("Console")
-                .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
-                .embed(
-                    CreateEmbed::new()
-                        .author(CreateEmbedAuthor::new("Starting server"))
-                        .colour(colours::branding::GREEN),
-                ),
-        )
-        .await?;
+    // Discord may be unreachable, in which case the remaining messages are saved for next time
+    let outboxes = instance::all().iter().flat_map(Instance::outboxes);
+    let flushed = futures::future::join_all(outboxes.clone().map(Outbox::flush));
+    if tokio::time::timeout(FLUSH_TIMEOUT, flushed).await.is_err() {
+        eprintln!("Could not deliver the remaining messages to Discord, saving them");
+    }
+
+    // The queue is also saved periodically, which would race with saving it here
+    deliveries.shutdown().await;
+
+    for outbox in outboxes {
+        if let Err(e) = outbox.save().await {
+            eprintln!("Could not save undelivered messages: {e:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    let _ = control::remove_stale(&control::socket_path());
+
+    eprintln!("Stopped wrapper");
 
-    let mut process = {
-        let Some(cmd_name) = args.next() else {
-            println!("Usage: {binary_name} <command>");
-            std::process::exit(1);
+    result
+}
+
+/// Something to post in the console channel.
+pub enum ConsoleMessage {
+    /// Text batched with the lines around it.
+    Line(Box<str>),
+    /// A message with a file attached, posted after the lines sent before it.
+    Attachment {
+        message: ExecuteWebhook,
+        name: &'static str,
+        data: Box<[u8]>,
+    },
+}
+
+impl From<String> for ConsoleMessage {
+    fn from(line: String) -> Self {
+        ConsoleMessage::Line(line.into())
+    }
+}
+
+impl From<&str> for ConsoleMessage {
+    fn from(line: &str) -> Self {
+        ConsoleMessage::Line(line.into())
+    }
+}
+
+/// Spawns a task batching lines sent to it into messages on the console webhook. The task
+/// finishes once every sender is dropped.
+fn console_logger(console: Outbox) -> (JoinHandle<()>, flume::Sender<ConsoleMessage>) {
+    let (tx, rx) = flume::unbounded::<ConsoleMessage>();
+
+    fn send_lines(console: &Outbox, buf: &mut String) {
+        let s = buf.chars().collect::<Vec<_>>();
+        let mut s = s.as_slice();
+
+        // Discord refuses messages that are empty or only whitespace
+        let send = |chunk: &[char]| {
+            if chunk.iter().all(|c| c.is_whitespace()) {
+                return;
+            }
+
+            console.send(
+                ExecuteWebhook::new()
+                    .username("Console")
+                    .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+                    .content(chunk.iter().collect::<String>()),
+            );
         };
 
-        let mut process = tokio::process::Command::new(cmd_name);
-        for arg in args {
-            process.arg(arg);
+        while s.len() > 2000 {
+            // Cut after the last line that fits, or mid-line if a single line is too long
+            let idx = s[..2000]
+                .iter()
+                .rposition(|c| *c == '\n')
+                .map_or(2000, |idx| idx + 1);
+
+            send(&s[..idx]);
+            s = &s[idx..];
         }
 
-        process
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?
-    };
+        send(s);
 
-    let Some(stdout) = process.stdout.take() else {
-        bail!("Could not get child stdout");
+        buf.clear();
+    }
+
+    let logger = tokio::task::spawn(async move {
+        let mut buf = String::with_capacity(4096);
+
+        while let Ok(msg) = rx.recv_async().await {
+            let mut next = Some(msg);
+            while let Some(msg) = next.take().or_else(|| rx.try_recv().ok()) {
+                match msg {
+                    ConsoleMessage::Line(line) => buf.push_str(&line),
+                    ConsoleMessage::Attachment {
+                        message,
+                        name,
+                        data,
+                    } => {
+                        send_lines(&console, &mut buf);
+                        console.send_file(message, name, &data);
+                    }
+                }
+            }
+
+            send_lines(&console, &mut buf);
+        }
+    });
+
+    (logger, tx)
+}
+
+pub fn announce(webhook: &Outbox, title: impl Into<String>, colour: Colour) {
+    webhook.send(
+        ExecuteWebhook::new()
+            .username("Console")
+            .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+            .embed(status_embed(title, colour)),
+    );
+}
+
+pub fn status_embed(title: impl Into<String>, colour: Colour) -> CreateEmbed {
+    CreateEmbed::new()
+        .author(CreateEmbedAuthor::new(title))
+        .colour(colour)
+}
+
+/// Describes a moderation command for the audit channel.
+fn moderation_embed(actor: Option<&[u8]>, action: &ModerationAction<'_>) -> CreateEmbed {
+    let actor = actor.map_or(Cow::Borrowed("The console"), |actor| actor.to_str_lossy());
+    let player = action.player().to_str_lossy();
+
+    let (title, colour) = match action {
+        ModerationAction::Kick { .. } => {
+            (format!("{actor} kicked {player}"), colours::branding::RED)
+        }
+        ModerationAction::Ban { .. } => {
+            (format!("{actor} banned {player}"), colours::branding::RED)
+        }
+        ModerationAction::Pardon { .. } => (
+            format!("{actor} unbanned {player}"),
+            colours::branding::GREEN,
+        ),
+        ModerationAction::Op { .. } => (
+            format!("{actor} made {player} an operator"),
+            colours::branding::YELLOW,
+        ),
+        ModerationAction::Deop { .. } => (
+            format!("{actor} removed {player} as an operator"),
+            colours::branding::YELLOW,
+        ),
+        ModerationAction::WhitelistAdd { .. } => (
+            format!("{actor} added {player} to the whitelist"),
+            colours::branding::GREEN,
+        ),
+        ModerationAction::WhitelistRemove { .. } => (
+            format!("{actor} removed {player} from the whitelist"),
+            colours::branding::RED,
+        ),
     };
 
-    let Some(mut stdin) = process.stdin.take() else {
-        bail!("Could not get child stdin")
+    let mut embed = CreateEmbed::new()
+        .author(
+            CreateEmbedAuthor::new(title)
+                .icon_url(format!("https://skinatar.firstdark.dev/avatar/{player}")),
+        )
+        .colour(colour);
+    if let Some(reason) = action.reason() {
+        embed = embed.description(reason.to_str_lossy());
+    }
+
+    embed
+}
+
+/// Summarises an error or warning with a stack trace.
+fn exception_embed(log: &GenericLog<'_>, exception: &ExceptionSummary<'_>) -> CreateEmbed {
+    let colour = match log.logger.level {
+        LogLevel::Warn => colours::branding::YELLOW,
+        _ => colours::branding::RED,
     };
 
-    let (tx, rx) = flume::unbounded();
-    COMMAND_CHANNEL.set(tx).unwrap();
+    let mut embed = CreateEmbed::new()
+        // Embed authors are limited to 256 characters
+        .author(CreateEmbedAuthor::new(
+            log.message
+                .to_str_lossy()
+                .chars()
+                .take(256)
+                .collect::<String>(),
+        ))
+        .description(format!(
+            "`{}`\nat `{}`",
+            exception.exception.to_str_lossy(),
+            exception.location.to_str_lossy()
+        ))
+        .colour(colour);
+
+    if let Some(root_cause) = exception.root_cause {
+        embed = embed.field(
+            "Caused by",
+            format!("`{}`", root_cause.to_str_lossy()),
+            false,
+        );
+    }
+
+    embed
+}
 
-    join_set.spawn(async move {
-        while let Ok(msg) = rx.recv_async().await {
-            stdin.write_all(&msg).await?;
-            stdin.write_u8(b'\n').await?;
-            stdin.flush().await?;
+/// How far along the server is in starting, as shown in the progress message.
+#[derive(Clone, Copy, Debug)]
+enum Progress {
+    Starting,
+    Preparing(u8),
+    /// The server is online, after starting for the given number of seconds.
+    Ready(f64),
+}
+
+/// Shows startup progress in a single message that is edited in place, until the sender is
+/// dropped.
+async fn show_progress(webhook: Outbox, mut progress: watch::Receiver<Progress>) {
+    let mut message: Option<MessageId> = None;
+
+    while progress.changed().await.is_ok() {
+        let current = *progress.borrow_and_update();
+        match current {
+            Progress::Starting => {}
+            Progress::Preparing(percent) => {
+                let embed = status_embed("Preparing spawn area", colours::branding::YELLOW)
+                    .description(progress_bar(percent));
+
+                match message {
+                    Some(message_id) => {
+                        let _ = webhook
+                            .edit(message_id, EditWebhookMessage::new().embed(embed))
+                            .await;
+                    }
+                    // Progress is only worth showing as it happens, so it is not queued
+                    None => {
+                        message = webhook
+                            .send_now(
+                                ExecuteWebhook::new()
+                                    .username("Console")
+                                    .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+                                    .embed(embed),
+                            )
+                            .await
+                            .ok()
+                            .map(|message| message.id);
+                    }
+                }
+
+                // Only the latest progress is shown once this has passed, which keeps the edits
+                // within Discord's rate limits
+                tokio::time::sleep(Duration::from_secs(3)).await;
+            }
+            Progress::Ready(duration) => {
+                let title = format!("Server is online (took {duration:.1}s)");
+                let edited = match message.take() {
+                    Some(message_id) => webhook
+                        .edit(
+                            message_id,
+                            EditWebhookMessage::new()
+                                .embed(status_embed(&title, colours::branding::GREEN)),
+                        )
+                        .await
+                        .is_ok(),
+                    None => false,
+                };
+
+                // The progress message could not be edited, such as while Discord is unreachable
+                if !edited {
+                    announce(&webhook, title, colours::branding::GREEN);
+                }
+            }
         }
+    }
+}
 
-        Ok(())
-    });
+fn progress_bar(percent: u8) -> String {
+    const WIDTH: usize = 20;
+
+    let filled = percent as usize * WIDTH / 100;
+    format!(
+        "`{}{}` {percent}%",
+        "█".repeat(filled),
+        "░".repeat(WIDTH - filled)
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub(crate) async fn read_lines(
+    reader: impl AsyncRead + Unpin,
+    stream: Stream,
+    lines: flume::Sender<(Stream, Vec<u8>)>,
+) -> Result<()> {
+    let mut input = BufReader::new(reader);
+
+    loop {
+        let mut buf = Vec::with_capacity(256);
+        if input.read_until(b'\n', &mut buf).await? == 0 {
+            break;
+        }
+
+        lines.send_async((stream, buf)).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn read_logs(
+    server: &'static Instance,
+    lines: flume::Receiver<(Stream, Vec<u8>)>,
+    log_to_console: flume::Sender<ConsoleMessage>,
+    recent_lines: Arc<Mutex<VecDeque<Box<str>>>>,
+) -> Result<()> {
+    let webhook = &server.webhook;
+
+    // Output of each server is labelled when there are several
+    let label = if instance::all().len() > 1 {
+        format!("[{}] ", server.name)
+    } else {
+        String::new()
+    };
+
+    // Startup progress is shown by a task of its own, so that the log loop never waits for Discord
+    let (progress, progress_rx) = watch::channel(Progress::Starting);
+    tokio::spawn(show_progress(webhook.clone(), progress_rx));
+
+    // Why each player's connection ended, shown once they leave
+    let mut disconnect_reasons: HashMap<String, String> = HashMap::new();
+
+    let mut thread_dump = threaddump::Capture::new(server);
+
+    // Each line is held until the next shows whether it continues the same entry
+    let mut pending: Option<(Stream, Vec<u8>)> = None;
+
+    loop {
+        let quiet_period = if thread_dump.is_capturing() {
+            Some(threaddump::QUIET_PERIOD)
+        } else if pending.is_some() {
+            Some(ENTRY_QUIET_PERIOD)
+        } else {
+            None
+        };
+
+        let received = match quiet_period {
+            Some(quiet_period) => tokio::time::timeout(quiet_period, lines.recv_async())
+                .await
+                .ok(),
+            None => Some(lines.recv_async().await),
+        };
+
+        let (stream, entry) = match received {
+            Some(Ok((stream, line))) => {
+                let s = line.to_str_lossy();
+                match stream {
+                    Stream::Stdout => print!("{label}{s}"),
+                    Stream::Stderr => eprint!("{label}{s}"),
+                }
+
+                if stream == Stream::Stdout && thread_dump.feed(&s) {
+                    continue;
+                }
+
+                {
+                    let mut recent_lines = recent_lines.lock();
+                    if recent_lines.len() == supervisor::RECENT_LINES {
+                        recent_lines.pop_front();
+                    }
+                    recent_lines.push_back(s.as_ref().into());
+                }
+
+                // Lines without a header, such as the frames of a stack trace, belong to the
+                // entry before them
+                if let Some((pending_stream, entry)) = &mut pending
+                    && *pending_stream == stream
+                    && Dialect::detect(&line).is_none()
+                {
+                    entry.extend_from_slice(&line);
+                    continue;
+                }
+
+                match pending.replace((stream, line)) {
+                    Some(entry) => entry,
+                    None => continue,
+                }
+            }
+            // The last entry is relayed before stopping
+            Some(Err(_)) => match pending.take() {
+                Some(entry) => entry,
+                None => break,
+            },
+            None => {
+                thread_dump.finish();
+                match pending.take() {
+                    Some(entry) => entry,
+                    None => continue,
+                }
+            }
+        };
 
-    let log_reader = tokio::task::spawn(async move {
-        let http = Http::new(&token);
-        let webhook = Webhook::from_url(&http, &crate::env::discord_webhook_url()).await?;
+        let s = entry.to_str_lossy();
+        let buf = entry.strip_suffix(b"\n").unwrap_or(&entry[..]);
+        if server.dialect.lock().is_none()
+            && let Some(dialect) = Dialect::detect(buf)
+        {
+            eprintln!("Detected {dialect} log format for {}", server.name);
+            *server.dialect.lock() = Some(dialect);
+        }
 
-        let mut input = BufReader::new(stdout);
-        let mut buf = Vec::with_capacity(16384);
+        let parsed = Log::parse(buf, server.dialect(), server.death_messages());
 
-        while let Ok(n) = input.read_until(b'\n', &mut buf).await {
-            if n == 0 {
+        let (log, span) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("error: {:?}", e);
                 continue;
             }
+        };
 
-            let s = buf[..n].to_str_lossy();
-            print!("{s}");
+        // Custom patterns see lines that are not otherwise understood, and `/say` lines since
+        // mods log `[Tag] ...` lines that look like them, but never chat so that players cannot
+        // trigger them
+        let custom = match log {
+            Log::Generic(_) | Log::Say(_) => server
+                .patterns
+                .iter()
+                .find_map(|pattern| Some((pattern, pattern.matches(&buf[span.into_range()])?))),
+            _ => None,
+        };
 
-            log_to_console.send(s.into())?;
+        if custom.is_none()
+            && let Some(event) = api::EventKind::from_log(&log, &buf[span.into_range()])
+        {
+            api::record(server, event);
+        }
 
-            let parsed = {
-                let parser = Log::parser();
-                parser.parse(&buf[..n - 1]).into_result()
+        if !matches!(log, Log::Progress(_)) {
+            let line: Arc<str> = match stream {
+                Stream::Stdout => s.as_ref().into(),
+                Stream::Stderr => format!("[stderr] {s}").into(),
             };
 
-            let (log, span) = match parsed {
-                Ok(parsed) => parsed,
-                Err(e) => {
-                    eprintln!("error: {:?}", e);
-                    buf.clear();
-                    continue;
-                }
+            let _ = server.log_lines.send(line.clone());
+
+            // Stack traces are attached as a file, which Discord shows collapsed
+            let exception = match &log {
+                Log::Generic(generic) => generic.exception().map(|exception| (generic, exception)),
+                _ => None,
             };
 
-            match &log {
-                Log::Chat(ChatLog {
-                    sender, message, ..
-                }) => {
-                    let sender: &str = &sender.to_str_lossy();
+            log_to_console.send(match exception {
+                Some((generic, exception)) => ConsoleMessage::Attachment {
+                    message: ExecuteWebhook::new()
+                        .username("Console")
+                        .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+                        .embed(exception_embed(generic, &exception)),
+                    name: "stacktrace.txt",
+                    data: line.as_bytes().into(),
+                },
+                None => line.as_ref().into(),
+            })?;
+        }
+
+        if let Some((pattern, fields)) = custom {
+            webhook.send(pattern.render(&fields));
+            api::record(
+                server,
+                api::EventKind::Custom {
+                    name: pattern.name.clone(),
+                    fields,
+                },
+            );
+            continue;
+        }
 
-                    let avatar: Cow<'_, str> = if sender == "[Server]" {
-                        Cow::Borrowed("https://skinatar.firstdark.dev/avatar/Console")
+        match &log {
+            Log::Chat(ChatLog {
+                sender, message, ..
+            }) => {
+                let sender: &str = &sender.to_str_lossy();
+
+                let avatar: Cow<'_, str> = if sender == "[Server]" {
+                    Cow::Borrowed("https://skinatar.firstdark.dev/avatar/Console")
+                } else {
+                    Cow::Owned(format!("https://skinatar.firstdark.dev/avatar/{sender}"))
+                };
+
+                webhook.send(
+                    ExecuteWebhook::new()
+                        .username(sender)
+                        .avatar_url(avatar)
+                        .content(message.to_str_lossy()),
+                );
+            }
+            Log::Emote(EmoteLog { player, action, .. }) => {
+                if let Some(outbox) = server.outbox(server.relays.emotes) {
+                    let sender: &str = &player.to_str_lossy();
+
+                    outbox.send(
+                        ExecuteWebhook::new()
+                            .username(sender)
+                            .avatar_url(format!("https://skinatar.firstdark.dev/avatar/{sender}"))
+                            .content(format!("*{}*", action.to_str_lossy())),
+                    );
+                }
+            }
+            Log::Say(SayLog {
+                sender, message, ..
+            }) => {
+                if let Some(outbox) = server.outbox(server.relays.say) {
+                    // Command blocks say things as `@`
+                    let name = sender.to_str_lossy();
+                    let (sender, avatar): (&str, Cow<'_, str>) = if name == "@" {
+                        (
+                            "Command Block",
+                            Cow::Borrowed("https://skinatar.firstdark.dev/avatar/Console"),
+                        )
                     } else {
-                        Cow::Owned(format!("https://skinatar.firstdark.dev/avatar/{sender}"))
+                        (
+                            &name,
+                            Cow::Owned(format!("https://skinatar.firstdark.dev/avatar/{name}")),
+                        )
                     };
 
-                    let _ = webhook
-                        .execute(
-                            &http,
-                            false,
-                            ExecuteWebhook::new()
-                                .username(sender)
-                                .avatar_url(avatar)
-                                .content(message.to_str_lossy()),
-                        )
-                        .await;
+                    outbox.send(
+                        ExecuteWebhook::new()
+                            .username(sender)
+                            .avatar_url(avatar)
+                            .content(message.to_str_lossy()),
+                    );
                 }
-                Log::List(ListUuidsLog { players, max }) => {
-                    let tx = interface::LIST_SENDER.get().unwrap();
-                    if tx.receiver_count() == 0 {
-                        continue;
-                    }
-
-                    let mut owned = Vec::with_capacity(players.len());
-                    for player in players {
-                        owned.push(OwnedPlayerData::try_from(player)?);
-                    }
+            }
+            Log::TeamChat(TeamChatLog {
+                team,
+                sender,
+                message,
+                ..
+            }) => {
+                if let Some(outbox) = server.outbox(server.relays.team_chat) {
+                    let sender: &str = &sender.to_str_lossy();
 
-                    let owned: Arc<[OwnedPlayerData]> = owned.into();
-                    let _ = tx.send(ListData {
-                        players: owned,
-                        max: *max,
-                    });
+                    outbox.send(
+                        ExecuteWebhook::new()
+                            .username(sender)
+                            .avatar_url(format!("https://skinatar.firstdark.dev/avatar/{sender}"))
+                            .content(format!(
+                                "**[{}]** {}",
+                                team.to_str_lossy(),
+                                message.to_str_lossy()
+                            )),
+                    );
                 }
-                Log::Join(JoinLog { player, .. }) => {
-                    let sender: &str = &player.to_str_lossy();
+            }
+            Log::Whisper(WhisperLog {
+                sender,
+                recipient,
+                message,
+                ..
+            }) => {
+                if let Some(outbox) = server.outbox(server.relays.whispers) {
+                    let sender: &str = &sender.to_str_lossy();
 
-                    let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
-
-                    let _ = webhook
-                        .execute(
-                            &http,
-                            false,
-                            ExecuteWebhook::new()
-                                .username(sender)
-                                .avatar_url(&avatar)
-                                .embed(
-                                    CreateEmbed::new()
-                                        .author(
-                                            CreateEmbedAuthor::new(format!("{sender} joined"))
-                                                .icon_url(&avatar),
-                                        )
-                                        .colour(colours::branding::GREEN),
-                                ),
-                        )
-                        .await;
+                    outbox.send(
+                        ExecuteWebhook::new()
+                            .username(sender)
+                            .avatar_url(format!("https://skinatar.firstdark.dev/avatar/{sender}"))
+                            .content(format!(
+                                "*to {}:* {}",
+                                recipient.to_str_lossy(),
+                                message.to_str_lossy()
+                            )),
+                    );
                 }
-                Log::Leave(LeaveLog { player, .. }) => {
-                    let sender: &str = &player.to_str_lossy();
+            }
+            Log::Join(JoinLog { player, .. }) => {
+                let sender: &str = &player.to_str_lossy();
+
+                let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
+
+                webhook.send(
+                    ExecuteWebhook::new()
+                        .username(sender)
+                        .avatar_url(&avatar)
+                        .embed(
+                            CreateEmbed::new()
+                                .author(
+                                    CreateEmbedAuthor::new(format!("{sender} joined"))
+                                        .icon_url(&avatar),
+                                )
+                                .colour(colours::branding::GREEN),
+                        ),
+                );
+            }
+            Log::Leave(LeaveLog { player, .. }) => {
+                let sender: &str = &player.to_str_lossy();
 
-                    let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
-
-                    let _ = webhook
-                        .execute(
-                            &http,
-                            false,
-                            ExecuteWebhook::new()
-                                .username(sender)
-                                .avatar_url(&avatar)
-                                .embed(
-                                    CreateEmbed::new()
-                                        .author(
-                                            CreateEmbedAuthor::new(format!("{sender} left"))
-                                                .icon_url(&avatar),
-                                        )
-                                        .colour(colours::branding::RED),
-                                ),
-                        )
-                        .await;
+                let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
+
+                let mut embed = CreateEmbed::new()
+                    .author(CreateEmbedAuthor::new(format!("{sender} left")).icon_url(&avatar))
+                    .colour(colours::branding::RED);
+                if let Some(reason) = disconnect_reasons.remove(sender) {
+                    embed = embed.description(reason);
                 }
-                Log::Advancement(AdvancementLog {
-                    player,
-                    advancement,
-                    ..
-                }) => {
-                    let sender: &str = &player.to_str_lossy();
 
-                    let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
-
-                    let _ = webhook
-                        .execute(
-                            &http,
-                            false,
-                            ExecuteWebhook::new()
-                                .username(sender)
-                                .avatar_url(&avatar)
-                                .embed(
-                                    CreateEmbed::new()
-                                        .author(
-                                            CreateEmbedAuthor::new(
-                                                buf[span.into_range()].to_str_lossy(),
-                                            )
-                                            .icon_url(&avatar),
-                                        )
-                                        .description(
-                                            ADVANCEMENTS
-                                                .get()
-                                                .and_then(|adv| {
-                                                    advancement
-                                                        .to_str()
-                                                        .ok()
-                                                        .and_then(|s| adv.get(s))
-                                                        .copied()
-                                                })
-                                                .unwrap_or_default(),
-                                        )
-                                        .colour(colours::branding::YELLOW),
-                                ),
-                        )
-                        .await;
+                webhook.send(
+                    ExecuteWebhook::new()
+                        .username(sender)
+                        .avatar_url(&avatar)
+                        .embed(embed),
+                );
+            }
+            Log::Disconnect(DisconnectLog { player, reason, .. }) => {
+                // Leaving normally is not worth mentioning
+                if &**reason != b"Disconnected" {
+                    disconnect_reasons.insert(
+                        player.to_str_lossy().into_owned(),
+                        reason.to_str_lossy().into_owned(),
+                    );
+                }
+            }
+            Log::Moderation(ModerationLog { actor, action, .. }) => {
+                if let Some(audit_webhook) = &server.audit_webhook {
+                    audit_webhook.send(
+                        ExecuteWebhook::new()
+                            .username("Console")
+                            .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+                            .embed(moderation_embed(*actor, action)),
+                    );
+                }
+            }
+            Log::Advancement(AdvancementLog {
+                player,
+                advancement,
+                ..
+            }) => {
+                let sender: &str = &player.to_str_lossy();
+
+                let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
+
+                webhook.send(
+                    ExecuteWebhook::new()
+                        .username(sender)
+                        .avatar_url(&avatar)
+                        .embed(
+                            CreateEmbed::new()
+                                .author(
+                                    CreateEmbedAuthor::new(buf[span.into_range()].to_str_lossy())
+                                        .icon_url(&avatar),
+                                )
+                                .description(
+                                    server
+                                        .lang
+                                        .lock()
+                                        .as_ref()
+                                        .and_then(|lang| {
+                                            advancement
+                                                .to_str()
+                                                .ok()
+                                                .and_then(|s| lang.advancements.get(s))
+                                                .copied()
+                                        })
+                                        .unwrap_or_default(),
+                                )
+                                .colour(colours::branding::YELLOW),
+                        ),
+                );
+            }
+            Log::Starting(StartingLog { version, .. }) => {
+                let version = version.to_str_lossy().into_owned();
+                *server.version.lock() = Some(version.as_str().into());
+
+                // Reading the mods again on every restart would only find the same messages
+                if server
+                    .lang
+                    .lock()
+                    .as_ref()
+                    .is_some_and(|lang| *lang.version == *version)
+                {
+                    continue;
                 }
-                Log::Starting(StartingLog { version, .. }) => {
-                    let version = version.to_str_lossy().into_owned();
-
-                    tokio::spawn(async move {
-                        let lang_file_name = {
-                            let mut name = language();
-                            name.push_str(".json");
-                            name
-                        };
 
-                        eprintln!("Looking for language files named {}", lang_file_name);
-                        let mut buf = Vec::new();
+                tokio::spawn(async move {
+                    let lang_file_name = {
+                        let mut name = language();
+                        name.push_str(".json");
+                        name
+                    };
 
-                        let mut death_messages = Vec::new();
-                        let mut advancements = HashMap::new();
+                    eprintln!("Looking for language files named {}", lang_file_name);
+                    let mut buf = Vec::new();
 
-                        let mut lang_string = String::new();
-                        let mut lang_ranges = Vec::new();
-                        let mut full_lang: HashMap<&'static str, &'static str> = HashMap::new();
+                    let mut death_messages = Vec::new();
+                    let mut advancements = HashMap::new();
 
-                        serde_json::from_slice::<HashMap<String, String>>(
-                            &reqwest::get(
-                                format!("https://assets.mcasset.cloud/{version}/assets/minecraft/lang/{lang_file_name}")
-                            )
-                            .await?
-                            .bytes()
-                            .await?
-                        ).unwrap_or_default().into_iter().for_each(|(k, v)| {
-                            let k_start = lang_string.len();
-                            lang_string.push_str(&k);
-                            let k_end = lang_string.len();
-                            let v_start = lang_string.len();
-                            lang_string.push_str(&v);
-                            let v_end = lang_string.len();
-                            lang_ranges.push((k_start..k_end, v_start..v_end));
-                        });
-
-                        let mods_folder = server_directory().join("mods");
-                        if let Ok(mod_paths) = jar::files(&mods_folder) {
-                            for path in mod_paths {
-                                let file = OpenOptions::new().read(true).open(&path)?;
-                                let mut archive = ZipArchive::new(file)?;
-
-                                for i in 0..archive.len() {
-                                    let mut file = archive.by_index(i)?;
-
-                                    if !file.is_file() {
-                                        continue;
-                                    }
+                    let mut lang_string = String::new();
+                    let mut lang_ranges = Vec::new();
+                    let mut full_lang: HashMap<&'static str, &'static str> = HashMap::new();
 
-                                    if let Some(name) = file.enclosed_name()
-                                        && name.file_name().is_some_and(|n| *n == *lang_file_name)
-                                    {
-                                        buf.clear();
-                                        file.read_to_end(&mut buf)?;
-
-                                        serde_json::from_slice::<HashMap<String, String>>(&buf)
-                                            .unwrap_or_default()
-                                            .into_iter()
-                                            .for_each(|(k, v)| {
-                                                let k_start = lang_string.len();
-                                                lang_string.push_str(&k);
-                                                let k_end = lang_string.len();
-                                                let v_start = lang_string.len();
-                                                lang_string.push_str(&v);
-                                                let v_end = lang_string.len();
-                                                lang_ranges.push((k_start..k_end, v_start..v_end));
-                                            });
-                                    }
+                    serde_json::from_slice::<HashMap<String, String>>(
+                        &reqwest::get(
+                            format!("https://assets.mcasset.cloud/{version}/assets/minecraft/lang/{lang_file_name}")
+                        )
+                        .await?
+                        .bytes()
+                        .await?
+                    ).unwrap_or_default().into_iter().for_each(|(k, v)| {
+                        let k_start = lang_string.len();
+                        lang_string.push_str(&k);
+                        let k_end = lang_string.len();
+                        let v_start = lang_string.len();
+                        lang_string.push_str(&v);
+                        let v_end = lang_string.len();
+                        lang_ranges.push((k_start..k_end, v_start..v_end));
+                    });
+
+                    let mods_folder = server.server_directory.join("mods");
+                    if let Ok(mod_paths) = jar::files(&mods_folder) {
+                        for path in mod_paths {
+                            let file = OpenOptions::new().read(true).open(&path)?;
+                            let mut archive = ZipArchive::new(file)?;
+
+                            for i in 0..archive.len() {
+                                let mut file = archive.by_index(i)?;
+
+                                if !file.is_file() {
+                                    continue;
                                 }
-                            }
-                        }
 
-                        lang_string.shrink_to_fit();
-                        let lang_string = lang_string.leak();
-                        for (k, v) in lang_ranges {
-                            full_lang.insert(&lang_string[k], &lang_string[v]);
+                                if let Some(name) = file.enclosed_name()
+                                    && name.file_name().is_some_and(|n| *n == *lang_file_name)
+                                {
+                                    buf.clear();
+                                    file.read_to_end(&mut buf)?;
+
+                                    serde_json::from_slice::<HashMap<String, String>>(&buf)
+                                        .unwrap_or_default()
+                                        .into_iter()
+                                        .for_each(|(k, v)| {
+                                            let k_start = lang_string.len();
+                                            lang_string.push_str(&k);
+                                            let k_end = lang_string.len();
+                                            let v_start = lang_string.len();
+                                            lang_string.push_str(&v);
+                                            let v_end = lang_string.len();
+                                            lang_ranges.push((k_start..k_end, v_start..v_end));
+                                        });
+                                }
+                            }
                         }
+                    }
 
-                        for (k, v) in &full_lang {
-                            if k.starts_with("death.") {
-                                let Some(victim) = v.find("%1$s") else {
-                                    continue;
-                                };
+                    lang_string.shrink_to_fit();
+                    let lang_string = lang_string.leak();
+                    for (k, v) in lang_ranges {
+                        full_lang.insert(&lang_string[k], &lang_string[v]);
+                    }
 
-                                let mut first = (victim, DeathMessageComponent::Victim);
-                                let mut second = (v.len(), DeathMessageComponent::Empty);
-                                let mut third = (v.len(), DeathMessageComponent::Empty);
+                    for (k, v) in &full_lang {
+                        if k.starts_with("death.") {
+                            let Some(victim) = v.find("%1$s") else {
+                                continue;
+                            };
+
+                            let mut first = (victim, DeathMessageComponent::Victim);
+                            let mut second = (v.len(), DeathMessageComponent::Empty);
+                            let mut third = (v.len(), DeathMessageComponent::Empty);
+
+                            if let Some(attacker) = v.find("%2$s") {
+                                if attacker < victim {
+                                    second = first;
+                                    first = (attacker, DeathMessageComponent::Attacker);
+                                } else {
+                                    second = (attacker, DeathMessageComponent::Attacker);
+                                }
+                            }
 
-                                if let Some(attacker) = v.find("%2$s") {
-                                    if attacker < victim {
+                            if let Some(weapon) = v.find("%3$s") {
+                                if matches!(second.1, DeathMessageComponent::Empty) {
+                                    if weapon < first.0 {
                                         second = first;
-                                        first = (attacker, DeathMessageComponent::Attacker);
+                                        first = (weapon, DeathMessageComponent::Weapon);
                                     } else {
-                                        second = (attacker, DeathMessageComponent::Attacker);
+                                        second = (weapon, DeathMessageComponent::Weapon);
                                     }
-                                }
-
-                                if let Some(weapon) = v.find("%3$s") {
-                                    if matches!(second.1, DeathMessageComponent::Empty) {
-                                        if weapon < first.0 {
-                                            second = first;
-                                            first = (weapon, DeathMessageComponent::Weapon);
-                                        } else {
-                                            second = (weapon, DeathMessageComponent::Weapon);
-                                        }
+                                } else {
+                                    if weapon < first.0 {
+                                        third = second;
+                                        second = first;
+                                        first = (weapon, DeathMessageComponent::Weapon);
+                                    } else if weapon < second.0 {
+                                        third = second;
+                                        second = (weapon, DeathMessageComponent::Weapon);
                                     } else {
-                                        if weapon < first.0 {
-                                            third = second;
-                                            second = first;
-                                            first = (weapon, DeathMessageComponent::Weapon);
-                                        } else if weapon < second.0 {
-                                            third = second;
-                                            second = (weapon, DeathMessageComponent::Weapon);
-                                        } else {
-                                            third = (weapon, DeathMessageComponent::Weapon);
-                                        }
+                                        third = (weapon, DeathMessageComponent::Weapon);
                                     }
                                 }
+                            }
 
-                                death_messages.push((
-                                    (&v[..first.0] as &'static str).as_bytes(),
-                                    first.1,
-                                    if first.0 + 4 < v.len() {
-                                        &v[first.0 + 4..second.0]
-                                    } else {
-                                        ""
-                                    }
-                                    .as_bytes(),
-                                    second.1,
-                                    if second.0 + 4 < v.len() {
-                                        &v[second.0 + 4..third.0]
-                                    } else {
-                                        ""
-                                    }
-                                    .as_bytes(),
-                                    third.1,
-                                    if third.0 + 4 < v.len() {
-                                        &v[third.0 + 4..]
-                                    } else {
-                                        ""
-                                    }
-                                    .as_bytes(),
-                                ));
-                            } else if k.starts_with("advancements.")
-                                && let Some(prefix) = k.strip_suffix(".title")
-                            {
-                                let mut desc_key = prefix.to_string();
-                                desc_key.push_str(".description");
-                                if let Some(desc) = full_lang.get(&*desc_key) {
-                                    advancements.insert(*v, *desc);
+                            death_messages.push((
+                                (&v[..first.0] as &'static str).as_bytes(),
+                                first.1,
+                                if first.0 + 4 < v.len() {
+                                    &v[first.0 + 4..second.0]
+                                } else {
+                                    ""
                                 }
+                                .as_bytes(),
+                                second.1,
+                                if second.0 + 4 < v.len() {
+                                    &v[second.0 + 4..third.0]
+                                } else {
+                                    ""
+                                }
+                                .as_bytes(),
+                                third.1,
+                                if third.0 + 4 < v.len() {
+                                    &v[third.0 + 4..]
+                                } else {
+                                    ""
+                                }
+                                .as_bytes(),
+                            ));
+                        } else if k.starts_with("advancements.")
+                            && let Some(prefix) = k.strip_suffix(".title")
+                        {
+                            let mut desc_key = prefix.to_string();
+                            desc_key.push_str(".description");
+                            if let Some(desc) = full_lang.get(&*desc_key) {
+                                advancements.insert(*v, *desc);
                             }
                         }
-                        advancements.shrink_to_fit();
-                        full_lang.shrink_to_fit();
-
-                        let death_len = death_messages.len();
-                        let _ = DEATH_MESSAGES
-                            .get_or_init(|| Box::leak(death_messages.into_boxed_slice()));
-                        let advancement_len = advancements.len();
-                        let _ = ADVANCEMENTS.get_or_init(|| advancements);
-                        eprintln!(
-                            "Initialized {} death messages and {} advancements from {} lang entries.\nKeys: {} bytes, Values: {} bytes",
-                            death_len,
-                            advancement_len,
-                            full_lang.len(),
-                            full_lang.keys().map(|&s| s.len()).sum::<usize>(),
-                            full_lang.values().map(|&s| s.len()).sum::<usize>()
-                        );
-                        let _ = LANG.get_or_init(|| full_lang);
-
-                        Ok::<_, Error>(())
+                    }
+                    advancements.shrink_to_fit();
+                    full_lang.shrink_to_fit();
+
+                    eprintln!(
+                        "Initialized {} death messages and {} advancements from {} lang entries.\nKeys: {} bytes, Values: {} bytes",
+                        death_messages.len(),
+                        advancements.len(),
+                        full_lang.len(),
+                        full_lang.keys().map(|&s| s.len()).sum::<usize>(),
+                        full_lang.values().map(|&s| s.len()).sum::<usize>()
+                    );
+                    *server.lang.lock() = Some(Lang {
+                        version: version.into(),
+                        death_messages: Box::leak(death_messages.into_boxed_slice()),
+                        advancements,
                     });
-                }
-                Log::Death(DeathLog { victim, .. }) => {
-                    let sender: &str = &victim.to_str_lossy();
-
-                    let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
-
-                    let _ = webhook
-                        .execute(
-                            &http,
-                            false,
-                            ExecuteWebhook::new()
-                                .username(sender)
-                                .avatar_url(&avatar)
-                                .embed(
-                                    CreateEmbed::new()
-                                        .author(
-                                            CreateEmbedAuthor::new(
-                                                buf[span.into_range()].to_str_lossy(),
-                                            )
-                                            .icon_url(&avatar),
-                                        )
-                                        .colour(colours::branding::RED),
-                                ),
-                        )
-                        .await;
-                }
-                _ => (),
-            }
-
-            buf.clear();
-        }
 
-        Ok::<(), Error>(())
-    });
-
-    let (input_fin_tx, input_fin_rx) = tokio::sync::oneshot::channel::<()>();
-    std::thread::spawn(|| {
-        let mut editor = rustyline::DefaultEditor::new().unwrap();
-
-        loop {
-            match editor.readline("") {
-                Ok(line) => {
-                    let _ = editor.add_history_entry(line.as_str());
-                    if let Err(e) = command_sync(line.as_bytes()) {
-                        eprintln!("Error sending command: {e:?}");
+                    Ok::<_, Error>(())
+                });
+            }
+            Log::Ready(ReadyLog { duration, .. }) => {
+                server.state.send_if_modified(|state| {
+                    let starting = *state == supervisor::State::Starting;
+                    if starting {
+                        *state = supervisor::State::Running;
                     }
-                }
-                Err(ReadlineError::Interrupted) => {
-                    eprintln!("Received CTRL+C, exiting.");
-                    break;
-                }
-                Err(ReadlineError::Eof) => {
-                    eprintln!("Received CTRL+D, exiting.");
-                    break;
-                }
-                Err(e) => eprintln!("Error reading line: {e:?}"),
+                    starting
+                });
+
+                eprintln!("Server is online");
+                progress.send_replace(Progress::Ready(*duration));
             }
+            Log::Progress(ProgressLog { percent, .. }) => {
+                progress.send_replace(Progress::Preparing(*percent));
+            }
+            Log::Death(DeathLog { victim, .. }) => {
+                let sender: &str = &victim.to_str_lossy();
+
+                let avatar = format!("https://skinatar.firstdark.dev/avatar/{sender}");
+
+                webhook.send(
+                    ExecuteWebhook::new()
+                        .username(sender)
+                        .avatar_url(&avatar)
+                        .embed(
+                            CreateEmbed::new()
+                                .author(
+                                    CreateEmbedAuthor::new(buf[span.into_range()].to_str_lossy())
+                                        .icon_url(&avatar),
+                                )
+                                .colour(colours::branding::RED),
+                        ),
+                );
+            }
+            _ => (),
         }
-
-        let _ = input_fin_tx.send(());
-    });
-
-    tokio::select! {
-        _ = input_fin_rx => {}
-        _ = signal_fin_rx => {}
-        Ok(_) = process.wait() => {}
-    }
-
-    if !matches!(process.try_wait(), Ok(Some(_))) {
-        eprintln!("Stopping server");
-        webhook
-            .execute(
-                &http,
-                false,
-                ExecuteWebhook::new()
-                    .username("Console")
-                    .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
-                    .embed(
-                        CreateEmbed::new()
-                            .author(CreateEmbedAuthor::new("Stopping server"))
-                            .colour(colours::branding::RED),
-                    ),
-            )
-            .await?;
-        command(*b"stop").await?;
-        let _ = process.wait().await;
     }
 
-    eprintln!("Stopping wrapper");
-
-    let _ = process.wait().await;
-    tokio::time::sleep(Duration::from_secs(1)).await;
-    log_reader.abort();
-    logger.await?;
-    join_set.abort_all();
-
-    eprintln!("Stopped wrapper");
-
-    Ok(())
-}
-
-pub async fn command(s: impl Into<Box<[u8]>>) -> Result<()> {
-    COMMAND_CHANNEL.get().unwrap().send_async(s.into()).await?;
-    Ok(())
-}
+    thread_dump.finish();
 
-pub fn command_sync(s: impl Into<Box<[u8]>>) -> Result<()> {
-    COMMAND_CHANNEL.get().unwrap().send(s.into())?;
     Ok(())
 }
 
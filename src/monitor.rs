@@ -0,0 +1,312 @@
+use std::time::{Duration, Instant};
+
+use eyre::bail;
+
+use crate::{ConsoleMessage, Result, instance::Instance, supervisor::State};
+
+/// Time between the two samples used to measure CPU usage.
+const SAMPLE_PERIOD: Duration = Duration::from_secs(1);
+
+pub struct Usage {
+    pub uptime: Duration,
+    /// Resident set size in bytes.
+    pub rss: u64,
+    /// CPU usage as a percentage of a single core.
+    pub cpu: f64,
+    pub threads: u64,
+    pub fds: usize,
+    /// Maximum heap size in bytes, if set with `-Xmx`.
+    pub max_heap: Option<u64>,
+}
+
+impl Usage {
+    pub fn rss_percent(&self) -> Option<f64> {
+        self.max_heap
+            .map(|max_heap| self.rss as f64 / max_heap as f64 * 100.0)
+    }
+}
+
+/// Measures the resource usage of the running server.
+pub async fn usage(server: &Instance) -> Result<Usage> {
+    let Some((pid, started)) = server.process() else {
+        bail!("The server is not running.");
+    };
+    let pid = jvm_pid(pid)?;
+
+    let first = sample(pid)?;
+    tokio::time::sleep(SAMPLE_PERIOD).await;
+    let second = sample(pid)?;
+
+    let ticks = second.cpu_ticks.saturating_sub(first.cpu_ticks) as f64;
+    let elapsed = (second.time - first.time).as_secs_f64();
+
+    Ok(Usage {
+        uptime: started.elapsed(),
+        rss: second.rss,
+        cpu: ticks / second.ticks_per_second as f64 / elapsed * 100.0,
+        threads: second.threads,
+        fds: second.fds,
+        max_heap: second.max_heap,
+    })
+}
+
+struct Sample {
+    time: Instant,
+    cpu_ticks: u64,
+    ticks_per_second: u64,
+    rss: u64,
+    threads: u64,
+    fds: usize,
+    max_heap: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+fn sample(pid: u32) -> Result<Sample> {
+    let time = Instant::now();
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat"))?;
+
+    // The command name may contain spaces, so fields are counted from the end of it
+    let Some((_, fields)) = stat.rsplit_once(')') else {
+        bail!("Could not parse /proc/{pid}/stat");
+    };
+    let fields = fields.split_whitespace().collect::<Vec<_>>();
+    let field = |n: usize| -> Result<u64> {
+        match fields.get(n - 3).map(|f| f.parse::<u64>()) {
+            Some(Ok(value)) => Ok(value),
+            _ => bail!("Could not parse field {n} of /proc/{pid}/stat"),
+        }
+    };
+
+    // SAFETY: `sysconf` has no memory safety preconditions.
+    let (ticks_per_second, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+
+    let fds = std::fs::read_dir(format!("/proc/{pid}/fd"))?.count();
+
+    let max_heap = std::fs::read(format!("/proc/{pid}/cmdline"))?
+        .split(|b| *b == 0)
+        .filter_map(|arg| arg.strip_prefix(b"-Xmx"))
+        .filter_map(|size| parse_size(std::str::from_utf8(size).ok()?))
+        .next_back();
+
+    Ok(Sample {
+        time,
+        cpu_ticks: field(14)? + field(15)?,
+        ticks_per_second: ticks_per_second.max(1) as u64,
+        rss: field(24)? * page_size.max(0) as u64,
+        threads: field(20)?,
+        fds,
+        max_heap,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sample(_pid: u32) -> Result<Sample> {
+    bail!("Process statistics are only available on Linux.");
+}
+
+/// Finds the JVM among `pid` and its descendants, since the server is often started through a
+/// shell script or a runner such as mc-server-runner rather than by running `java` directly.
+#[cfg(target_os = "linux")]
+pub fn jvm_pid(pid: u32) -> Result<u32> {
+    let is_java = |pid: u32| {
+        std::fs::read_to_string(format!("/proc/{pid}/comm")).is_ok_and(|comm| comm.trim() == "java")
+    };
+
+    if is_java(pid) {
+        return Ok(pid);
+    }
+
+    // Parent of every process, read from field 4 of its stat file
+    let mut parents = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let Some(child) = entry?
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(stat) = std::fs::read_to_string(format!("/proc/{child}/stat")) else {
+            continue;
+        };
+        if let Some(parent) = stat
+            .rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().nth(1)?.parse::<u32>().ok())
+        {
+            parents.push((parent, child));
+        }
+    }
+
+    let mut queue = std::collections::VecDeque::from([pid]);
+    while let Some(pid) = queue.pop_front() {
+        for &(_, child) in parents.iter().filter(|(parent, _)| *parent == pid) {
+            if is_java(child) {
+                return Ok(child);
+            }
+            queue.push_back(child);
+        }
+    }
+
+    bail!("Could not find the server's Java process.");
+}
+
+/// Processes cannot be inspected elsewhere, so the server is assumed to run `java` directly.
+#[cfg(not(target_os = "linux"))]
+pub fn jvm_pid(pid: u32) -> Result<u32> {
+    Ok(pid)
+}
+
+/// Parses a JVM memory size such as `4G`, `4096m` or `4294967296`.
+#[cfg(target_os = "linux")]
+fn parse_size(s: &str) -> Option<u64> {
+    let (number, multiplier) = match s.as_bytes().last()? {
+        b'k' | b'K' => (&s[..s.len() - 1], 1 << 10),
+        b'm' | b'M' => (&s[..s.len() - 1], 1 << 20),
+        b'g' | b'G' => (&s[..s.len() - 1], 1 << 30),
+        b't' | b'T' => (&s[..s.len() - 1], 1 << 40),
+        _ => (s, 1),
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    format!("{value:.1} {unit}")
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes, secs) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m {secs}s"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+pub struct AlertPolicy {
+    interval: Duration,
+    rss_percent: Option<f64>,
+    cpu_percent: Option<f64>,
+}
+
+impl AlertPolicy {
+    /// Returns `None` if no thresholds are set.
+    pub fn from_env() -> Option<Self> {
+        let rss_percent = crate::env::alert_rss_percent();
+        let cpu_percent = crate::env::alert_cpu_percent();
+        if rss_percent.is_none() && cpu_percent.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            interval: Duration::from_secs(crate::env::alert_interval().unwrap_or(60)),
+            rss_percent,
+            cpu_percent,
+        })
+    }
+}
+
+/// Periodically measures the server's resource usage and reports to the console when it
+/// crosses the configured thresholds.
+pub async fn run(
+    server: &Instance,
+    policy: AlertPolicy,
+    log_to_console: flume::Sender<ConsoleMessage>,
+) -> Result<()> {
+    let mut rss_alerted = false;
+    let mut cpu_alerted = false;
+
+    loop {
+        tokio::time::sleep(policy.interval).await;
+        if server.state() != State::Running {
+            rss_alerted = false;
+            cpu_alerted = false;
+            continue;
+        }
+
+        let usage = match usage(server).await {
+            Ok(usage) => usage,
+            Err(e) => {
+                eprintln!("Could not measure server resource usage: {e}");
+                continue;
+            }
+        };
+
+        if let (Some(threshold), Some(percent)) = (policy.rss_percent, usage.rss_percent()) {
+            let above = percent >= threshold;
+            if above != rss_alerted {
+                rss_alerted = above;
+                log_to_console.send(
+                    if above {
+                        format!(
+                            "[mcglue] Server is using {} of memory, {percent:.0}% of -Xmx\n",
+                            format_bytes(usage.rss)
+                        )
+                    } else {
+                        format!("[mcglue] Server memory usage is back to {percent:.0}% of -Xmx\n")
+                    }
+                    .into(),
+                )?;
+            }
+        }
+
+        if let Some(threshold) = policy.cpu_percent {
+            let above = usage.cpu >= threshold;
+            if above != cpu_alerted {
+                cpu_alerted = above;
+                log_to_console.send(
+                    if above {
+                        format!("[mcglue] Server is using {:.0}% CPU\n", usage.cpu)
+                    } else {
+                        format!("[mcglue] Server CPU usage is back to {:.0}%\n", usage.cpu)
+                    }
+                    .into(),
+                )?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_jvm_sizes() {
+        assert_eq!(parse_size("4G"), Some(4 << 30));
+        assert_eq!(parse_size("4096m"), Some(4 << 30));
+        assert_eq!(parse_size("512K"), Some(512 << 10));
+        assert_eq!(parse_size("4294967296"), Some(4 << 30));
+        assert_eq!(parse_size("99999999999T"), None);
+        assert_eq!(parse_size("G"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn formats_bytes_and_durations() {
+        assert_eq!(format_bytes(512), "512.0 B");
+        assert_eq!(format_bytes(3 << 29), "1.5 GiB");
+        assert_eq!(format_duration(Duration::from_secs(75)), "1m 15s");
+        assert_eq!(format_duration(Duration::from_secs(90061)), "1d 1h 1m");
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use eyre::bail;
+use parking_lot::Mutex;
+use poise::serenity_prelude::{
+    EditWebhookMessage, ExecuteWebhook, Http, Message, MessageId, Webhook,
+};
+use reqwest::{
+    StatusCode,
+    header::HeaderMap,
+    multipart::{Form, Part},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, watch};
+
+use crate::Result;
+
+/// Shortest time to wait before trying to reach Discord again.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest time to wait before trying to reach Discord again.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often the queue is saved while it holds messages.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A message waiting to be delivered, as the JSON body of a webhook request.
+#[derive(Clone, Serialize, Deserialize)]
+struct Delivery {
+    payload: serde_json::Value,
+    /// Attachments are only kept in memory, so they are lost if mcglue exits before delivering
+    /// them.
+    #[serde(skip)]
+    files: Vec<(String, Vec<u8>)>,
+}
+
+enum Failure {
+    /// Discord could not be reached or asked to try again later, after at least the given time.
+    Retry(Option<Duration>),
+    /// Discord refused the message itself, so sending it again would not help.
+    Rejected(String),
+}
+
+/// Messages for a webhook, delivered in order by a background task. Messages are kept while
+/// Discord is unreachable and saved to `state_file` so that they survive mcglue restarting, and
+/// nothing that relays to Discord has to wait for it.
+#[derive(Clone)]
+pub struct Outbox {
+    pending: Arc<Mutex<VecDeque<Delivery>>>,
+    /// Number of messages waiting to be delivered.
+    backlog: Arc<watch::Sender<usize>>,
+    /// Counts changes to the queue, so that it is only saved again once it has changed.
+    generation: Arc<AtomicU64>,
+    queued: Arc<Notify>,
+    client: reqwest::Client,
+    http: Arc<Http>,
+    url: Arc<str>,
+    /// Resolved the first time a message is sent or edited directly.
+    webhook: Arc<Mutex<Option<Webhook>>>,
+    state_file: Arc<PathBuf>,
+}
+
+impl Outbox {
+    /// Creates an outbox, loading the messages a previous run could not deliver. Messages are
+    /// only delivered once [`Outbox::deliver`] is running.
+    pub fn new(token: &str, url: &str, state_file: PathBuf) -> Result<Self> {
+        let pending = match std::fs::read_to_string(&state_file) {
+            // A damaged line only loses that message rather than keeping the server from starting
+            Ok(saved) => saved
+                .lines()
+                .enumerate()
+                .filter_map(|(i, line)| match serde_json::from_str(line) {
+                    Ok(delivery) => Some(delivery),
+                    Err(e) => {
+                        eprintln!(
+                            "Skipping invalid message on line {} of {state_file:?}: {e}",
+                            i + 1
+                        );
+                        None
+                    }
+                })
+                .collect::<VecDeque<Delivery>>(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        if !pending.is_empty() {
+            eprintln!(
+                "Loaded {} undelivered messages from {state_file:?}",
+                pending.len()
+            );
+        }
+
+        Ok(Self {
+            backlog: Arc::new(watch::Sender::new(pending.len())),
+            generation: Arc::new(AtomicU64::new(0)),
+            pending: Arc::new(Mutex::new(pending)),
+            queued: Arc::new(Notify::new()),
+            client: reqwest::Client::new(),
+            http: Arc::new(Http::new(token)),
+            url: url.into(),
+            webhook: Arc::new(Mutex::new(None)),
+            state_file: Arc::new(state_file),
+        })
+    }
+
+    /// Queues a message for delivery.
+    pub fn send(&self, message: ExecuteWebhook) {
+        self.push(message, Vec::new());
+    }
+
+    /// Queues a message for delivery with a file attached to it.
+    pub fn send_file(&self, message: ExecuteWebhook, name: &str, data: &[u8]) {
+        self.push(message, vec![(name.to_owned(), data.to_vec())]);
+    }
+
+    fn push(&self, message: ExecuteWebhook, files: Vec<(String, Vec<u8>)>) {
+        let payload = match serde_json::to_value(&message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                eprintln!("Could not serialize webhook message: {e}");
+                return;
+            }
+        };
+
+        let mut pending = self.pending.lock();
+        pending.push_back(Delivery { payload, files });
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.backlog.send_replace(pending.len());
+        self.queued.notify_one();
+    }
+
+    /// Number of messages waiting to be delivered.
+    pub fn backlog(&self) -> usize {
+        *self.backlog.borrow()
+    }
+
+    /// Sends a message right away and returns it so that it can be edited later, failing instead
+    /// of jumping the queue if earlier messages have not been delivered yet.
+    pub async fn send_now(&self, message: ExecuteWebhook) -> Result<Message> {
+        if self.backlog() > 0 {
+            bail!("Earlier messages have not been delivered yet");
+        }
+
+        let webhook = self.webhook().await?;
+        match webhook.execute(&self.http, true, message).await? {
+            Some(message) => Ok(message),
+            None => bail!("Discord did not return the message"),
+        }
+    }
+
+    pub async fn edit(&self, message_id: MessageId, edit: EditWebhookMessage) -> Result<()> {
+        let webhook = self.webhook().await?;
+        webhook.edit_message(&self.http, message_id, edit).await?;
+        Ok(())
+    }
+
+    /// Waits for every queued message to be delivered.
+    pub async fn flush(&self) {
+        let _ = self
+            .backlog
+            .subscribe()
+            .wait_for(|backlog| *backlog == 0)
+            .await;
+    }
+
+    /// Saves the messages that have not been delivered yet, or removes the state file if there
+    /// are none.
+    pub async fn save(&self) -> Result<()> {
+        let saved = {
+            let pending = self.pending.lock();
+            let mut saved = String::new();
+            for delivery in pending.iter() {
+                saved.push_str(&serde_json::to_string(delivery)?);
+                saved.push('\n');
+            }
+            saved
+        };
+
+        if saved.is_empty() {
+            return match tokio::fs::remove_file(&*self.state_file).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        if let Some(parent) = self.state_file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Replace the file in one step so that it is never left half written
+        let temporary = self.state_file.with_extension("tmp");
+        tokio::fs::write(&temporary, saved).await?;
+        tokio::fs::rename(&temporary, &*self.state_file).await?;
+
+        Ok(())
+    }
+
+    async fn webhook(&self) -> Result<Webhook> {
+        if let Some(webhook) = self.webhook.lock().clone() {
+            return Ok(webhook);
+        }
+
+        let webhook = Webhook::from_url(&self.http, &self.url).await?;
+        *self.webhook.lock() = Some(webhook.clone());
+        Ok(webhook)
+    }
+
+    /// Saves the queue every [`SAVE_INTERVAL`] while it holds messages, and removes the saved
+    /// messages once they have all been delivered. The task has to be stopped before the final
+    /// [`Outbox::save`].
+    pub async fn persist(self) {
+        let mut saved = self.state_file.exists();
+        let mut saved_generation = None;
+
+        loop {
+            tokio::time::sleep(SAVE_INTERVAL).await;
+
+            let generation = self.generation.load(Ordering::Relaxed);
+            let backlog = self.backlog();
+            if saved_generation == Some(generation) || (backlog == 0 && !saved) {
+                continue;
+            }
+
+            match self.save().await {
+                Ok(()) => {
+                    saved = backlog > 0;
+                    saved_generation = Some(generation);
+                }
+                Err(e) => eprintln!("Could not save undelivered messages: {e:?}"),
+            }
+        }
+    }
+
+    /// Delivers queued messages in order, retrying each until Discord accepts or rejects it.
+    pub async fn deliver(self) {
+        let mut backoff = MIN_BACKOFF;
+        let mut reachable = true;
+
+        loop {
+            let next = self.pending.lock().front().cloned();
+            let Some(delivery) = next else {
+                self.queued.notified().await;
+                continue;
+            };
+
+            match self.post(&delivery).await {
+                Ok(()) => {
+                    if !reachable {
+                        reachable = true;
+                        eprintln!(
+                            "Reached Discord again, delivering {} queued messages",
+                            self.backlog() - 1
+                        );
+                    }
+
+                    backoff = MIN_BACKOFF;
+                }
+                Err(Failure::Rejected(e)) => {
+                    eprintln!("Discord rejected a webhook message, dropping it: {e}");
+                }
+                Err(Failure::Retry(retry_after)) => {
+                    if reachable {
+                        reachable = false;
+                        eprintln!("Could not reach Discord, queueing messages");
+                    }
+
+                    match retry_after {
+                        Some(retry_after) => tokio::time::sleep(retry_after).await,
+                        None => {
+                            tokio::time::sleep(backoff).await;
+                            backoff = (backoff * 2).min(MAX_BACKOFF);
+                        }
+                    }
+
+                    continue;
+                }
+            }
+
+            let mut pending = self.pending.lock();
+            pending.pop_front();
+            self.generation.fetch_add(1, Ordering::Relaxed);
+            self.backlog.send_replace(pending.len());
+        }
+    }
+
+    async fn post(&self, delivery: &Delivery) -> Result<(), Failure> {
+        let request = self.client.post(&*self.url);
+        let request = if delivery.files.is_empty() {
+            request.json(&delivery.payload)
+        } else {
+            let mut form = Form::new().text("payload_json", delivery.payload.to_string());
+            for (i, (name, data)) in delivery.files.iter().enumerate() {
+                form = form.part(
+                    format!("files[{i}]"),
+                    Part::bytes(data.clone()).file_name(name.clone()),
+                );
+            }
+            request.multipart(form)
+        };
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(_) => return Err(Failure::Retry(None)),
+        };
+
+        let status = response.status();
+        let headers = response.headers().clone();
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Failure::Retry(Some(
+                seconds(&headers, "retry-after").unwrap_or(MIN_BACKOFF),
+            )));
+        }
+
+        if status.is_server_error() {
+            return Err(Failure::Retry(None));
+        }
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(Failure::Rejected(format!("{status} {body}")));
+        }
+
+        // Wait out the rate limit before it is hit rather than after
+        if headers
+            .get("x-ratelimit-remaining")
+            .is_some_and(|remaining| remaining == "0")
+            && let Some(reset_after) = seconds(&headers, "x-ratelimit-reset-after")
+        {
+            tokio::time::sleep(reset_after).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a header holding a number of seconds, which Discord may give with a fraction.
+fn seconds(headers: &HeaderMap, name: &str) -> Option<Duration> {
+    let value = headers.get(name)?.to_str().ok()?.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(value).ok()
+}
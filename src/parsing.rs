@@ -4,6 +4,7 @@ use std::{
     borrow::Cow,
     fmt::{Debug, Display},
     ops::Deref,
+    str::FromStr,
     sync::{Arc, LazyLock, OnceLock},
 };
 
@@ -11,7 +12,7 @@ use bstr::ByteSlice;
 use chumsky::{prelude::*, span::Span};
 use uuid::Uuid;
 
-use crate::{DEATH_MESSAGES, DeathMessageComponent};
+use crate::{DeathMessage, DeathMessageComponent};
 
 type LoggerParserExtra<'src> = extra::Full<Rich<'src, u8>, (), Logger<'src>>;
 
@@ -24,6 +25,28 @@ enum PartialLog<'src> {
         sender: &'src [u8],
         message: &'src [u8],
     },
+    Emote {
+        secure: Option<bool>,
+        player: &'src [u8],
+        action: &'src [u8],
+    },
+    Say {
+        secure: Option<bool>,
+        sender: &'src [u8],
+        message: &'src [u8],
+    },
+    TeamChat {
+        secure: Option<bool>,
+        team: &'src [u8],
+        sender: &'src [u8],
+        message: &'src [u8],
+    },
+    Whisper {
+        secure: Option<bool>,
+        sender: &'src [u8],
+        recipient: &'src [u8],
+        message: &'src [u8],
+    },
     List {
         data: ListUuidsLog<'src>,
     },
@@ -33,6 +56,14 @@ enum PartialLog<'src> {
     Leave {
         player: &'src [u8],
     },
+    Disconnect {
+        player: &'src [u8],
+        reason: &'src [u8],
+    },
+    Moderation {
+        actor: Option<&'src [u8]>,
+        action: ModerationAction<'src>,
+    },
     Advancement {
         player: &'src [u8],
         advancement: &'src [u8],
@@ -40,6 +71,12 @@ enum PartialLog<'src> {
     Starting {
         version: &'src [u8],
     },
+    Ready {
+        duration: f64,
+    },
+    Progress {
+        percent: u8,
+    },
     Death {
         victim: &'src [u8],
         attacker: &'src [u8],
@@ -54,18 +91,54 @@ enum PartialLog<'src> {
 pub enum Log<'src> {
     Generic(GenericLog<'src>),
     Chat(ChatLog<'src>),
+    Emote(EmoteLog<'src>),
+    Say(SayLog<'src>),
+    TeamChat(TeamChatLog<'src>),
+    Whisper(WhisperLog<'src>),
     List(ListUuidsLog<'src>),
     Join(JoinLog<'src>),
     Leave(LeaveLog<'src>),
+    Disconnect(DisconnectLog<'src>),
+    Moderation(ModerationLog<'src>),
     Advancement(AdvancementLog<'src>),
     Starting(StartingLog<'src>),
+    Ready(ReadyLog),
+    Progress(ProgressLog),
     Death(DeathLog<'src>),
     Unknown(&'src [u8]),
 }
 
 impl<'src> Log<'src> {
-    pub fn parser()
-    -> impl Parser<'src, &'src [u8], (Self, SimpleSpan<usize>), extra::Err<Rich<'src, u8>>> {
+    /// Parses an entry in the given dialect, falling back to the vanilla layout that mcglue uses
+    /// for lines it synthesizes itself. Lines after the first are the entry's body, such as a
+    /// stack trace, and are only kept by [`Log::Generic`].
+    pub fn parse(
+        entry: &'src [u8],
+        dialect: Dialect,
+        death_messages: &'static [DeathMessage],
+    ) -> Result<(Self, SimpleSpan<usize>), Vec<Rich<'src, u8>>> {
+        let (line, body) = entry.split_once_str(b"\n").unwrap_or((entry, b""));
+
+        let mut parsed = Self::parser(dialect, death_messages)
+            .parse(line)
+            .into_result()?;
+        if dialect != Dialect::Vanilla && matches!(parsed.0, Log::Unknown(_)) {
+            parsed = Self::parser(Dialect::Vanilla, death_messages)
+                .parse(line)
+                .into_result()?;
+        }
+
+        if let (Log::Generic(generic), _) = &mut parsed {
+            generic.body = body;
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn parser(
+        dialect: Dialect,
+        death_messages: &'static [DeathMessage],
+    ) -> impl Parser<'src, &'src [u8], (Self, SimpleSpan<usize>), extra::Err<Rich<'src, u8>>> {
         trait OnlyIfLogger<'src, I, O, E, P>
         where
             I: Input<'src>,
@@ -73,6 +146,8 @@ impl<'src> Log<'src> {
             P: Parser<'src, I, O, E>,
         {
             fn only_if_logger(self, level: LogLevel, name: &[u8]) -> impl Parser<'src, I, O, E>;
+
+            fn only_if_level(self, level: LogLevel) -> impl Parser<'src, I, O, E>;
         }
 
         impl<'src, I, O, E, P> OnlyIfLogger<'src, I, O, E, P> for P
@@ -83,7 +158,12 @@ impl<'src> Log<'src> {
         {
             fn only_if_logger(self, level: LogLevel, name: &[u8]) -> impl Parser<'src, I, O, E> {
                 self.contextual()
-                    .configure(move |_, ctx: &Logger<'src>| ctx.level == level && ctx.name == name)
+                    .configure(move |_, ctx: &Logger<'src>| ctx.level == level && ctx.is(name))
+            }
+
+            fn only_if_level(self, level: LogLevel) -> impl Parser<'src, I, O, E> {
+                self.contextual()
+                    .configure(move |_, ctx: &Logger<'src>| ctx.level == level)
             }
         }
 
@@ -105,13 +185,20 @@ impl<'src> Log<'src> {
             .map_err(|e| Rich::custom(*e.span(), "Could not find non-whitespace slice"))
             .to_slice();
 
+        let secure = just::<'src, _, _, LoggerParserExtra<'src>>(b"Not Secure")
+            .delimited_by(just(b'['), just(b']'))
+            .or_not()
+            .map(|o| o.is_none())
+            .then_ignore(just(b' '))
+            .or_not();
+
+        let rest = any::<'src, &'src [u8], LoggerParserExtra<'src>>()
+            .repeated()
+            .at_least(1)
+            .to_slice();
+
         let chat = group((
-            just::<'src, _, _, LoggerParserExtra<'src>>(b"Not Secure")
-                .delimited_by(just(b'['), just(b']'))
-                .or_not()
-                .map(|o| o.is_none())
-                .then_ignore(just(b' '))
-                .or_not(),
+            secure,
             choice((
                 any()
                     .filter(|b| *b != b'>')
@@ -130,6 +217,103 @@ impl<'src> Log<'src> {
             sender,
             message,
         })
+        .contextual()
+        // Paper logs chat from its own threads
+        .configure(|_, ctx: &Logger<'src>| {
+            ctx.level == LogLevel::Info
+                && (ctx.is(b"Server thread") || ctx.name.starts_with(b"Async Chat Thread"))
+        });
+
+        let emote = group((
+            secure,
+            just(b"* ").ignore_then(non_whitespace_slice),
+            just(b' ').ignore_then(rest),
+        ))
+        .map(|(secure, player, action)| PartialLog::Emote {
+            secure,
+            player,
+            action,
+        })
+        .map_err(|e| Rich::custom(*e.span(), "Could not parse as emote"))
+        .only_if_logger(LogLevel::Info, b"Server thread");
+
+        // Only names that players and command blocks can have, which rules out tags with spaces
+        // such as `[FTB Quests]`
+        let say = group((
+            secure,
+            choice((
+                any()
+                    .filter(|b: &u8| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.'))
+                    .repeated()
+                    .at_least(1)
+                    .at_most(16)
+                    .to_slice(),
+                just(b"@".as_slice()),
+            ))
+            .delimited_by(just(b'['), just(b']')),
+            just(b' ').ignore_then(rest),
+        ))
+        .map(|(secure, sender, message)| PartialLog::Say {
+            secure,
+            sender,
+            message,
+        })
+        .map_err(|e| Rich::custom(*e.span(), "Could not parse as `/say` message"))
+        .contextual()
+        // Plugins log `[Name] ...` from the server thread too, so this is only trusted when the
+        // dialect names the thread rather than assumed like for other messages
+        .configure(|_, ctx: &Logger<'src>| {
+            ctx.level == LogLevel::Info && ctx.name == b"Server thread"
+        });
+
+        let team_chat = group((
+            secure,
+            just(b"-> ").ignore_then(
+                any()
+                    .filter(|b| *b != b']')
+                    .repeated()
+                    .at_least(1)
+                    .to_slice()
+                    .delimited_by(just(b'['), just(b']')),
+            ),
+            just(b' ').ignore_then(
+                any()
+                    .filter(|b| *b != b'>')
+                    .repeated()
+                    .at_least(1)
+                    .to_slice()
+                    .delimited_by(just(b'<'), just(b'>')),
+            ),
+            just(b' ').ignore_then(rest),
+        ))
+        .map(|(secure, team, sender, message)| PartialLog::TeamChat {
+            secure,
+            team,
+            sender,
+            message,
+        })
+        .map_err(|e| Rich::custom(*e.span(), "Could not parse as team message"))
+        .only_if_logger(LogLevel::Info, b"Server thread");
+
+        let whisper = group((
+            secure,
+            non_whitespace_slice,
+            just(b" whispers to ").ignore_then(
+                any()
+                    .filter(|b: &u8| *b != b':' && !b.is_ascii_whitespace())
+                    .repeated()
+                    .at_least(1)
+                    .to_slice(),
+            ),
+            just(b": ").ignore_then(rest),
+        ))
+        .map(|(secure, sender, recipient, message)| PartialLog::Whisper {
+            secure,
+            sender,
+            recipient,
+            message,
+        })
+        .map_err(|e| Rich::custom(*e.span(), "Could not parse as whisper"))
         .only_if_logger(LogLevel::Info, b"Server thread");
 
         let list = group((
@@ -193,6 +377,79 @@ impl<'src> Log<'src> {
             .map_err(|e| Rich::custom(*e.span(), "Could not parse as leave message"))
             .only_if_logger(LogLevel::Info, b"Server thread");
 
+        let disconnect = group((
+            non_whitespace_slice,
+            just(b" lost connection: ").ignore_then(rest),
+        ))
+        .map(|(player, reason)| PartialLog::Disconnect { player, reason })
+        .map_err(|e| Rich::custom(*e.span(), "Could not parse as disconnect message"))
+        .only_if_logger(LogLevel::Info, b"Server thread");
+
+        let name = any::<'src, &'src [u8], LoggerParserExtra<'src>>()
+            .filter(|b: &u8| *b != b':' && *b != b']' && !b.is_ascii_whitespace())
+            .repeated()
+            .at_least(1)
+            .to_slice();
+
+        // Feedback from players' commands is wrapped in `[actor: ...]`, so the reason stops
+        // before a closing bracket at the end of the line
+        let reason = just(b": ").ignore_then(
+            any()
+                .and_is(just(b']').then(end()).not())
+                .repeated()
+                .at_least(1)
+                .to_slice(),
+        );
+
+        let moderation_action = choice((
+            just(b"Kicked ")
+                .ignore_then(name)
+                .then(reason)
+                .map(|(player, reason)| ModerationAction::Kick { player, reason }),
+            just(b"Banned ")
+                .ignore_then(name)
+                .then(reason)
+                .map(|(player, reason)| ModerationAction::Ban { player, reason }),
+            just(b"Unbanned ")
+                .ignore_then(name)
+                .map(|player| ModerationAction::Pardon { player }),
+            just(b"Made ")
+                .ignore_then(name)
+                .then_ignore(just(b" no longer a server operator"))
+                .map(|player| ModerationAction::Deop { player }),
+            just(b"Made ")
+                .ignore_then(name)
+                .then_ignore(just(b" a server operator"))
+                .map(|player| ModerationAction::Op { player }),
+            just(b"Added ")
+                .ignore_then(name)
+                .then_ignore(just(b" to the whitelist"))
+                .map(|player| ModerationAction::WhitelistAdd { player }),
+            just(b"Removed ")
+                .ignore_then(name)
+                .then_ignore(just(b" from the whitelist"))
+                .map(|player| ModerationAction::WhitelistRemove { player }),
+        ));
+
+        let moderation = group((
+            just(b'[')
+                .ignore_then(name)
+                .then_ignore(just(b": "))
+                .or_not(),
+            moderation_action,
+            just(b']').or_not(),
+        ))
+        .then_ignore(end())
+        .try_map(|(actor, action, closed), span| {
+            if actor.is_some() != closed.is_some() {
+                return Err(Rich::custom(span, "Unbalanced brackets"));
+            }
+
+            Ok(PartialLog::Moderation { actor, action })
+        })
+        .map_err(|e| Rich::custom(*e.span(), "Could not parse as moderation command feedback"))
+        .only_if_logger(LogLevel::Info, b"Server thread");
+
         let advancement = non_whitespace_slice
             .then_ignore(choice((
                 just(b" has made the advancement ".as_slice()),
@@ -223,16 +480,40 @@ impl<'src> Log<'src> {
             .map(|version| PartialLog::Starting { version })
             .only_if_logger(LogLevel::Info, b"Server thread");
 
+        let ready = just::<_, _, LoggerParserExtra<'src>>(b"Done (")
+            .ignore_then(
+                any::<'src, &'src [u8], LoggerParserExtra<'src>>()
+                    .filter(|b: &u8| b.is_ascii_digit() || *b == b'.')
+                    .repeated()
+                    .at_least(1)
+                    .to_slice()
+                    .try_map(|s: &[u8], span| {
+                        std::str::from_utf8(s)
+                            .ok()
+                            .and_then(|s| s.parse::<f64>().ok())
+                            .ok_or_else(|| Rich::custom(span, "Could not parse startup duration"))
+                    }),
+            )
+            .then_ignore(just(b"s)!"))
+            .then_ignore(any().repeated())
+            .map(|duration| PartialLog::Ready { duration })
+            .map_err(|e| Rich::custom(*e.span(), "Could not parse as ready message"))
+            .only_if_logger(LogLevel::Info, b"Server thread");
+
+        // Logged from worker threads rather than the server thread
+        let progress = just::<_, _, LoggerParserExtra<'src>>(b"Preparing spawn area: ")
+            .ignore_then(text::int(10).try_map(as_u64))
+            .then_ignore(just(b'%'))
+            .map(|percent| PartialLog::Progress {
+                percent: percent.min(100) as u8,
+            })
+            .map_err(|e| Rich::custom(*e.span(), "Could not parse as progress message"))
+            .only_if_level(LogLevel::Info);
+
         let death = custom::<_, &[u8], _, LoggerParserExtra<'src>>(move |inp| {
             let cursor = inp.cursor();
-            let Some(death_messages) = DEATH_MESSAGES.get() else {
-                return Err(Rich::custom(
-                    inp.span_from(&inp.cursor()..),
-                    "Death messages not initialized",
-                ));
-            };
 
-            'death_message: for &(a, ar, b, br, c, cr, d) in death_messages.iter() {
+            'death_message: for &(a, ar, b, br, c, cr, d) in death_messages {
                 let mut slice = inp.slice_from(&cursor..);
                 if slice.is_empty() {
                     return Err(Rich::custom(
@@ -333,22 +614,27 @@ impl<'src> Log<'src> {
 
         let partial_logs = choice((
             chat,
+            emote,
+            say,
+            team_chat,
+            whisper,
             join,
             leave,
+            disconnect,
+            moderation,
             advancement,
             list,
             starting,
+            ready,
+            progress,
             death,
             generic,
         ));
 
+        let (time, logger) = dialect.header();
         group((
-            HmsTime::parser()
-                .delimited_by(just(b'['), just(b']'))
-                .then_ignore(just(b' ')),
-            Logger::parser()
-                .then_ignore(just(b": ".as_slice()))
-                .then_with_ctx(partial_logs.map_with(|parsed, e| (parsed, e.span()))),
+            time,
+            logger.then_with_ctx(partial_logs.map_with(|parsed, e| (parsed, e.span()))),
         ))
         .map(|(time, (logger, (partial, span)))| {
             (
@@ -357,6 +643,7 @@ impl<'src> Log<'src> {
                         time,
                         logger,
                         message,
+                        body: b"",
                     }),
                     PartialLog::Chat {
                         secure,
@@ -368,9 +655,63 @@ impl<'src> Log<'src> {
                         sender,
                         message,
                     }),
+                    PartialLog::Emote {
+                        secure,
+                        player,
+                        action,
+                    } => Self::Emote(EmoteLog {
+                        time,
+                        secure,
+                        player,
+                        action,
+                    }),
+                    PartialLog::Say {
+                        secure,
+                        sender,
+                        message,
+                    } => Self::Say(SayLog {
+                        time,
+                        secure,
+                        sender,
+                        message,
+                    }),
+                    PartialLog::TeamChat {
+                        secure,
+                        team,
+                        sender,
+                        message,
+                    } => Self::TeamChat(TeamChatLog {
+                        time,
+                        secure,
+                        team,
+                        sender,
+                        message,
+                    }),
+                    PartialLog::Whisper {
+                        secure,
+                        sender,
+                        recipient,
+                        message,
+                    } => Self::Whisper(WhisperLog {
+                        time,
+                        secure,
+                        sender,
+                        recipient,
+                        message,
+                    }),
                     PartialLog::List { data } => Self::List(data),
                     PartialLog::Join { player } => Self::Join(JoinLog { time, player }),
                     PartialLog::Leave { player } => Self::Leave(LeaveLog { time, player }),
+                    PartialLog::Disconnect { player, reason } => Self::Disconnect(DisconnectLog {
+                        time,
+                        player,
+                        reason,
+                    }),
+                    PartialLog::Moderation { actor, action } => Self::Moderation(ModerationLog {
+                        time,
+                        actor,
+                        action,
+                    }),
                     PartialLog::Advancement {
                         player,
                         advancement,
@@ -382,6 +723,10 @@ impl<'src> Log<'src> {
                     PartialLog::Starting { version } => {
                         Self::Starting(StartingLog { time, version })
                     }
+                    PartialLog::Ready { duration } => Self::Ready(ReadyLog { time, duration }),
+                    PartialLog::Progress { percent } => {
+                        Self::Progress(ProgressLog { time, percent })
+                    }
                     PartialLog::Death {
                         victim,
                         attacker,
@@ -410,6 +755,51 @@ pub struct GenericLog<'src> {
     pub time: HmsTime,
     pub logger: Logger<'src>,
     pub message: &'src [u8],
+    /// Lines logged without a header after the message, empty if there are none.
+    pub body: &'src [u8],
+}
+
+impl<'src> GenericLog<'src> {
+    /// Summarises the stack trace in the body, if the entry is a warning or error with one.
+    pub fn exception(&self) -> Option<ExceptionSummary<'src>> {
+        if !matches!(
+            self.logger.level,
+            LogLevel::Warn | LogLevel::Error | LogLevel::Fatal
+        ) {
+            return None;
+        }
+
+        let mut lines = self.body.lines().map(<[u8]>::trim_ascii);
+        let exception = lines.next().filter(|line| !line.is_empty())?;
+
+        let mut location = None;
+        let mut root_cause = None;
+        for line in lines {
+            if let Some(frame) = line.strip_prefix(b"at ") {
+                location.get_or_insert(frame);
+            } else if let Some(cause) = line.strip_prefix(b"Caused by: ") {
+                root_cause = Some(cause);
+            }
+        }
+
+        Some(ExceptionSummary {
+            exception,
+            // Bodies that are not stack traces are relayed as they are
+            location: location?,
+            root_cause,
+        })
+    }
+}
+
+/// The parts of a stack trace worth showing at a glance.
+#[derive(Clone, Debug)]
+pub struct ExceptionSummary<'src> {
+    /// The exception's class and message, such as `java.lang.IllegalStateException: Closed`.
+    pub exception: &'src [u8],
+    /// The frame the exception was thrown from.
+    pub location: &'src [u8],
+    /// The innermost exception that caused it, if any.
+    pub root_cause: Option<&'src [u8]>,
 }
 
 #[derive(Clone, Debug)]
@@ -420,6 +810,44 @@ pub struct ChatLog<'src> {
     pub message: &'src [u8],
 }
 
+/// An action sent with `/me`.
+#[derive(Clone, Debug)]
+pub struct EmoteLog<'src> {
+    pub time: HmsTime,
+    pub secure: Option<bool>,
+    pub player: &'src [u8],
+    pub action: &'src [u8],
+}
+
+/// A message broadcast with `/say` by a player, or by a command block as `@`.
+#[derive(Clone, Debug)]
+pub struct SayLog<'src> {
+    pub time: HmsTime,
+    pub secure: Option<bool>,
+    pub sender: &'src [u8],
+    pub message: &'src [u8],
+}
+
+/// A message sent to a player's team with `/teammsg`.
+#[derive(Clone, Debug)]
+pub struct TeamChatLog<'src> {
+    pub time: HmsTime,
+    pub secure: Option<bool>,
+    pub team: &'src [u8],
+    pub sender: &'src [u8],
+    pub message: &'src [u8],
+}
+
+/// A private message sent with `/msg`, `/tell` or `/w`.
+#[derive(Clone, Debug)]
+pub struct WhisperLog<'src> {
+    pub time: HmsTime,
+    pub secure: Option<bool>,
+    pub sender: &'src [u8],
+    pub recipient: &'src [u8],
+    pub message: &'src [u8],
+}
+
 #[derive(Clone, Debug)]
 pub struct JoinLog<'src> {
     pub time: HmsTime,
@@ -432,6 +860,86 @@ pub struct LeaveLog<'src> {
     pub player: &'src [u8],
 }
 
+/// Why a player's connection ended, logged just before they leave.
+#[derive(Clone, Debug)]
+pub struct DisconnectLog<'src> {
+    pub time: HmsTime,
+    pub player: &'src [u8],
+    pub reason: &'src [u8],
+}
+
+/// Feedback from a moderation command.
+#[derive(Clone, Debug)]
+pub struct ModerationLog<'src> {
+    pub time: HmsTime,
+    /// Player who ran the command, or `None` if it was run from the console.
+    pub actor: Option<&'src [u8]>,
+    pub action: ModerationAction<'src>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ModerationAction<'src> {
+    Kick {
+        player: &'src [u8],
+        reason: &'src [u8],
+    },
+    Ban {
+        player: &'src [u8],
+        reason: &'src [u8],
+    },
+    Pardon {
+        player: &'src [u8],
+    },
+    Op {
+        player: &'src [u8],
+    },
+    Deop {
+        player: &'src [u8],
+    },
+    WhitelistAdd {
+        player: &'src [u8],
+    },
+    WhitelistRemove {
+        player: &'src [u8],
+    },
+}
+
+impl<'src> ModerationAction<'src> {
+    pub fn player(&self) -> &'src [u8] {
+        match self {
+            ModerationAction::Kick { player, .. }
+            | ModerationAction::Ban { player, .. }
+            | ModerationAction::Pardon { player }
+            | ModerationAction::Op { player }
+            | ModerationAction::Deop { player }
+            | ModerationAction::WhitelistAdd { player }
+            | ModerationAction::WhitelistRemove { player } => player,
+        }
+    }
+
+    pub fn reason(&self) -> Option<&'src [u8]> {
+        match self {
+            ModerationAction::Kick { reason, .. } | ModerationAction::Ban { reason, .. } => {
+                Some(reason)
+            }
+            _ => None,
+        }
+    }
+
+    /// Short name of the action, as reported by the HTTP API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModerationAction::Kick { .. } => "kick",
+            ModerationAction::Ban { .. } => "ban",
+            ModerationAction::Pardon { .. } => "pardon",
+            ModerationAction::Op { .. } => "op",
+            ModerationAction::Deop { .. } => "deop",
+            ModerationAction::WhitelistAdd { .. } => "whitelist_add",
+            ModerationAction::WhitelistRemove { .. } => "whitelist_remove",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AdvancementLog<'src> {
     pub time: HmsTime,
@@ -445,6 +953,19 @@ pub struct StartingLog<'src> {
     pub version: &'src [u8],
 }
 
+#[derive(Clone, Debug)]
+pub struct ReadyLog {
+    pub time: HmsTime,
+    /// Seconds taken to start the server
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgressLog {
+    pub time: HmsTime,
+    pub percent: u8,
+}
+
 #[derive(Clone, Debug)]
 pub struct DeathLog<'src> {
     pub time: HmsTime,
@@ -523,6 +1044,7 @@ impl HmsTime {
 
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct Logger<'src> {
+    /// Name of the thread that logged the line, or empty if the dialect does not include it.
     pub name: &'src [u8],
     pub level: LogLevel,
 }
@@ -534,6 +1056,12 @@ impl Display for Logger<'_> {
 }
 
 impl<'src> Logger<'src> {
+    /// Whether the line was logged by the thread `name`, assuming it was if the dialect does not
+    /// say.
+    pub fn is(&self, name: &[u8]) -> bool {
+        self.name.is_empty() || self.name == name
+    }
+
     pub fn parser() -> impl Parser<'src, &'src [u8], Logger<'src>, extra::Err<Rich<'src, u8>>> {
         let not_slash = any().filter(|b: &u8| *b != b'/');
 
@@ -560,7 +1088,7 @@ pub enum LogLevel {
 
 impl Display for LogLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(&self, f)
+        Debug::fmt(self, f)
     }
 }
 
@@ -577,6 +1105,123 @@ impl LogLevel {
     }
 }
 
+type HeaderParser<'src, O> = Boxed<'src, 'src, &'src [u8], O, extra::Err<Rich<'src, u8>>>;
+
+/// Layout of the header before each log message, which differs between server software.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// `[12:00:00] [Server thread/INFO]: `, also used by Paper and Spigot in `logs/latest.log`.
+    Vanilla,
+    /// `[12:00:00 INFO]: `, printed to the console by Paper and Spigot.
+    Paper,
+    /// `[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: `, used by Forge and NeoForge,
+    /// optionally with the date and milliseconds in the timestamp.
+    Forge,
+    /// `[12:00:00] [Server thread/INFO] (Minecraft) `.
+    Fabric,
+}
+
+impl Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vanilla" => Ok(Dialect::Vanilla),
+            "paper" | "spigot" => Ok(Dialect::Paper),
+            "forge" | "neoforge" => Ok(Dialect::Forge),
+            "fabric" => Ok(Dialect::Fabric),
+            _ => Err(eyre::eyre!(
+                "Unknown log dialect {s:?}, expected vanilla, paper, spigot, forge, neoforge or fabric"
+            )),
+        }
+    }
+}
+
+impl Dialect {
+    /// Returns the dialect whose header `line` starts with. Headers of the dialects are checked
+    /// up to the start of the message, so at most one can match.
+    pub fn detect(line: &[u8]) -> Option<Self> {
+        [
+            Dialect::Forge,
+            Dialect::Fabric,
+            Dialect::Paper,
+            Dialect::Vanilla,
+        ]
+        .into_iter()
+        .find(|dialect| {
+            let (time, logger) = dialect.header();
+            time.then(logger).lazy().parse(line).has_output()
+        })
+    }
+
+    /// Returns parsers for the timestamp and for the logger up to the start of the message.
+    fn header<'src>(
+        self,
+    ) -> (
+        HeaderParser<'src, HmsTime>,
+        HeaderParser<'src, Logger<'src>>,
+    ) {
+        let bracketed_time = HmsTime::parser()
+            .delimited_by(just(b'['), just(b']'))
+            .then_ignore(just(b' '));
+
+        match self {
+            Dialect::Vanilla => (
+                bracketed_time.boxed(),
+                Logger::parser().then_ignore(just(b": ")).boxed(),
+            ),
+            Dialect::Paper => (
+                just(b'[')
+                    .ignore_then(HmsTime::parser())
+                    .then_ignore(just(b' '))
+                    .boxed(),
+                LogLevel::parser()
+                    .then_ignore(just(b"]: "))
+                    .map(|level| Logger { name: b"", level })
+                    .boxed(),
+            ),
+            Dialect::Forge => {
+                let digits = |n| any().filter(u8::is_ascii_digit).repeated().exactly(n);
+                let date = group((
+                    digits(2),
+                    any().filter(u8::is_ascii_alphabetic).repeated().exactly(3),
+                    digits(4),
+                    just(b' '),
+                ));
+                let millis = just(b'.').then(any().filter(u8::is_ascii_digit).repeated());
+
+                (
+                    date.or_not()
+                        .ignore_then(HmsTime::parser())
+                        .then_ignore(millis.or_not())
+                        .delimited_by(just(b'['), just(b']'))
+                        .then_ignore(just(b' '))
+                        .boxed(),
+                    Logger::parser()
+                        .then_ignore(just(b" ["))
+                        .then_ignore(any().filter(|b: &u8| *b != b']').repeated())
+                        .then_ignore(just(b"]: "))
+                        .boxed(),
+                )
+            }
+            Dialect::Fabric => (
+                bracketed_time.boxed(),
+                Logger::parser()
+                    .then_ignore(just(b" ("))
+                    .then_ignore(any().filter(|b: &u8| *b != b')').repeated())
+                    .then_ignore(just(b") "))
+                    .boxed(),
+            ),
+        }
+    }
+}
+
 fn as_u64<'src>(src: &'src [u8], span: SimpleSpan) -> Result<u64, Rich<'src, u8, SimpleSpan>> {
     btoi::btou::<u64>(src).map_err(|e| Rich::custom(span, e.to_string()))
 }
@@ -600,3 +1245,251 @@ impl Identifier {
         .map(|(namespace, _, path, _)| Identifier { namespace, path })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Log<'_> {
+        let line = line.as_bytes();
+        let dialect = Dialect::detect(line).expect("no dialect matches the line");
+        Log::parse(line, dialect, &[])
+            .expect("the line does not parse")
+            .0
+    }
+
+    #[test]
+    fn detects_dialects() {
+        let cases = [
+            (
+                "[12:00:00] [Server thread/INFO]: Steve joined the game",
+                Dialect::Vanilla,
+            ),
+            ("[12:00:00 INFO]: Steve joined the game", Dialect::Paper),
+            (
+                "[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: Steve joined the game",
+                Dialect::Forge,
+            ),
+            (
+                "[01Jan2025 12:00:00.123] [Server thread/INFO] [minecraft/MinecraftServer]: Steve joined the game",
+                Dialect::Forge,
+            ),
+            (
+                "[12:00:00] [Server thread/INFO] (Minecraft) Steve joined the game",
+                Dialect::Fabric,
+            ),
+        ];
+
+        for (line, dialect) in cases {
+            assert_eq!(Dialect::detect(line.as_bytes()), Some(dialect), "{line}");
+            assert!(
+                matches!(
+                    parse(line),
+                    Log::Join(JoinLog {
+                        player: b"Steve",
+                        ..
+                    })
+                ),
+                "{line}"
+            );
+        }
+
+        assert_eq!(
+            Dialect::detect(b"\tat net.minecraft.server.Main.main(Main.java:1)"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_emotes_and_whispers() {
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: * Steve waves"),
+            Log::Emote(EmoteLog {
+                player: b"Steve",
+                action: b"waves",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: [Not Secure] Steve whispers to Alex: hi there"),
+            Log::Whisper(WhisperLog {
+                secure: Some(false),
+                sender: b"Steve",
+                recipient: b"Alex",
+                message: b"hi there",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: -> [Red Team] <Steve> go left"),
+            Log::TeamChat(TeamChatLog {
+                team: b"Red Team",
+                sender: b"Steve",
+                message: b"go left",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_say_only_from_the_server_thread() {
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: [Steve] hello"),
+            Log::Say(SayLog {
+                sender: b"Steve",
+                message: b"hello",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: [@] beep"),
+            Log::Say(SayLog { sender: b"@", .. })
+        ));
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: [FTB Quests] Steve completed a chapter"),
+            Log::Generic(_)
+        ));
+        // Paper's console does not say which thread logged a line
+        assert!(matches!(
+            parse("[12:00:00 INFO]: [LuckPerms] Loading configuration..."),
+            Log::Generic(_)
+        ));
+    }
+
+    #[test]
+    fn parses_disconnects() {
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: Steve lost connection: Timed out"),
+            Log::Disconnect(DisconnectLog {
+                player: b"Steve",
+                reason: b"Timed out",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_moderation() {
+        fn action(line: &str) -> (Option<&[u8]>, ModerationAction<'_>) {
+            match parse(line) {
+                Log::Moderation(ModerationLog { actor, action, .. }) => (actor, action),
+                log => panic!("{line} parsed as {log:?}"),
+            }
+        }
+
+        let (actor, kick) = action("[12:00:00] [Server thread/INFO]: Kicked Steve: Spamming");
+        assert_eq!(actor, None);
+        assert!(matches!(
+            kick,
+            ModerationAction::Kick {
+                player: b"Steve",
+                reason: b"Spamming"
+            }
+        ));
+
+        let (actor, ban) =
+            action("[12:00:00] [Server thread/INFO]: [Alex: Banned Steve: Griefing [again]]");
+        assert_eq!(actor, Some(b"Alex".as_slice()));
+        assert!(matches!(
+            ban,
+            ModerationAction::Ban {
+                player: b"Steve",
+                reason: b"Griefing [again]"
+            }
+        ));
+
+        let cases: [(&str, fn(&ModerationAction<'_>) -> bool); 5] = [
+            ("Unbanned Steve", |a| {
+                matches!(a, ModerationAction::Pardon { .. })
+            }),
+            ("Made Steve a server operator", |a| {
+                matches!(a, ModerationAction::Op { .. })
+            }),
+            ("Made Steve no longer a server operator", |a| {
+                matches!(a, ModerationAction::Deop { .. })
+            }),
+            ("Added Steve to the whitelist", |a| {
+                matches!(a, ModerationAction::WhitelistAdd { .. })
+            }),
+            ("Removed Steve from the whitelist", |a| {
+                matches!(a, ModerationAction::WhitelistRemove { .. })
+            }),
+        ];
+
+        for (message, expected) in cases {
+            let line = format!("[12:00:00] [Server thread/INFO]: {message}");
+            let (_, action) = action(&line);
+            assert!(expected(&action), "{message}");
+            assert_eq!(action.player(), b"Steve");
+        }
+
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: Banned IP 127.0.0.1: Griefing"),
+            Log::Generic(_)
+        ));
+        assert!(matches!(
+            parse("[12:00:00] [Server thread/INFO]: [Alex: Unbanned Steve"),
+            Log::Generic(_)
+        ));
+    }
+
+    #[test]
+    fn parses_deaths_from_the_given_messages() {
+        use DeathMessageComponent::*;
+
+        static DEATH_MESSAGES: [DeathMessage; 1] = [(
+            b"",
+            Victim,
+            b" was slain by ",
+            Attacker,
+            b" using ",
+            Weapon,
+            b"",
+        )];
+        let line = b"[12:00:00] [Server thread/INFO]: Steve was slain by Zombie using Iron Sword";
+
+        let Log::Death(log) = Log::parse(line, Dialect::Vanilla, &DEATH_MESSAGES)
+            .unwrap()
+            .0
+        else {
+            panic!("not parsed as a death");
+        };
+        assert_eq!(log.victim, b"Steve");
+        assert_eq!(log.attacker, b"Zombie");
+        assert_eq!(log.weapon, b"Iron Sword");
+
+        // Servers whose messages are not loaded yet do not recognise any deaths
+        assert!(matches!(
+            Log::parse(line, Dialect::Vanilla, &[]).unwrap().0,
+            Log::Generic(_)
+        ));
+    }
+
+    #[test]
+    fn summarises_stack_traces() {
+        let entry = b"[12:00:00] [Server thread/ERROR]: Encountered an unexpected exception\n\
+java.lang.IllegalStateException: Closed\n\
+\tat net.minecraft.server.Main.run(Main.java:10)\n\
+Caused by: java.io.IOException: Broken pipe\n\
+\tat java.io.Stream.write(Stream.java:1)";
+
+        let Log::Generic(log) = Log::parse(entry, Dialect::Vanilla, &[]).unwrap().0 else {
+            panic!("not parsed as a generic log");
+        };
+        assert_eq!(log.message, b"Encountered an unexpected exception");
+
+        let exception = log.exception().expect("no exception found");
+        assert_eq!(
+            exception.exception,
+            b"java.lang.IllegalStateException: Closed"
+        );
+        assert_eq!(
+            exception.location,
+            b"net.minecraft.server.Main.run(Main.java:10)"
+        );
+        assert_eq!(
+            exception.root_cause,
+            Some(b"java.io.IOException: Broken pipe".as_slice())
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+use bstr::ByteSlice;
+use chumsky::prelude::*;
+use eyre::{bail, eyre};
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedAuthor, ExecuteWebhook, colours};
+use serde::Deserialize;
+
+use crate::Result;
+
+/// Most places a field is tried at before giving up on matching a line against a template.
+const MAX_MATCH_STEPS: usize = 100_000;
+
+/// Values captured from a line by a [`Pattern`], by field name.
+pub type Fields = BTreeMap<Box<str>, Box<str>>;
+
+/// Text with `{field}` placeholders, where `{{` and `}}` stand for literal braces.
+#[derive(Clone, Debug)]
+struct Template(Box<[Segment]>);
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Text(Box<str>),
+    Field(Box<str>),
+}
+
+impl FromStr for Template {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Template::parser().parse(s).into_result().map_err(|errors| {
+            eyre!(
+                "Invalid template {s:?}: {}",
+                errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Template {
+    fn parser<'src>() -> impl Parser<'src, &'src str, Template, extra::Err<Rich<'src, char>>> {
+        let literal = choice((just("{{").to('{'), just("}}").to('}'), none_of("{}")))
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .map(|text| Segment::Text(text.into()));
+
+        let field = text::ident()
+            .delimited_by(just('{'), just('}'))
+            .map(|name: &str| Segment::Field(name.into()));
+
+        choice((literal, field))
+            .repeated()
+            .collect::<Vec<_>>()
+            .map(|segments| Template(segments.into()))
+    }
+
+    fn fields(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|segment| match segment {
+            Segment::Field(name) => Some(&**name),
+            Segment::Text(_) => None,
+        })
+    }
+
+    fn render(&self, fields: &Fields) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => &**text,
+                Segment::Field(name) => fields.get(name).map_or("", |value| &**value),
+            })
+            .collect()
+    }
+
+    /// Matches the whole of `message`, capturing each field as the shortest non-empty text that
+    /// lets the rest of the template match. Gives up on lines that take too long to match, since
+    /// each field may have to be tried at every place the text after it appears.
+    fn captures(&self, message: &[u8]) -> Option<Fields> {
+        fn captures<'a>(
+            segments: &'a [Segment],
+            message: &'a [u8],
+            fields: &mut Vec<(&'a str, &'a [u8])>,
+            steps: &mut usize,
+        ) -> bool {
+            let Some((segment, rest)) = segments.split_first() else {
+                return message.is_empty();
+            };
+
+            match segment {
+                Segment::Text(text) => message
+                    .strip_prefix(text.as_bytes())
+                    .is_some_and(|message| captures(rest, message, fields, steps)),
+                // The last field takes whatever is left
+                Segment::Field(_) if rest.is_empty() && message.is_empty() => false,
+                Segment::Field(name) if rest.is_empty() => {
+                    fields.push((&**name, message));
+                    true
+                }
+                Segment::Field(name) => (1..=message.len()).any(|len| {
+                    let Some(steps_left) = steps.checked_sub(1) else {
+                        return false;
+                    };
+                    *steps = steps_left;
+
+                    // Only worth trying where the text that follows the field appears
+                    if let Some(Segment::Text(text)) = rest.first()
+                        && !message[len..].starts_with(text.as_bytes())
+                    {
+                        return false;
+                    }
+
+                    fields.push((&**name, &message[..len]));
+                    if captures(rest, &message[len..], fields, steps) {
+                        return true;
+                    }
+                    fields.pop();
+                    false
+                }),
+            }
+        }
+
+        let mut fields = Vec::new();
+        let mut steps = MAX_MATCH_STEPS;
+        captures(&self.0, message, &mut fields, &mut steps).then(|| {
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.to_str_lossy().into()))
+                .collect()
+        })
+    }
+}
+
+/// An embed colour written as `#rrggbb`.
+#[derive(Clone, Copy, Debug)]
+struct Colour(poise::serenity_prelude::Colour);
+
+impl<'de> Deserialize<'de> for Colour {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let colour = String::deserialize(deserializer)?;
+        match colour
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        {
+            Some(colour) => Ok(Colour(colour.into())),
+            None => Err(serde::de::Error::custom(format!(
+                "{colour:?} is not a colour like #f1c40f"
+            ))),
+        }
+    }
+}
+
+/// A line that a modpack or plugin logs, relayed as an event of its own.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pattern {
+    /// Name of the events the pattern emits, as reported by the HTTP API.
+    pub name: Box<str>,
+    /// The message to match, with a placeholder for each field to capture.
+    pattern: Template,
+    /// Title of the embed relayed to Discord.
+    title: Template,
+    description: Option<Template>,
+    /// Colour of the embed, yellow by default.
+    colour: Option<Colour>,
+    /// Field holding the name of the player the event is about, whose skin is shown with it.
+    player: Option<Box<str>>,
+}
+
+impl Pattern {
+    /// Returns the fields captured from a message the server logged, if it matches.
+    pub fn matches(&self, message: &[u8]) -> Option<Fields> {
+        self.pattern.captures(message)
+    }
+
+    /// Renders the event as a message for the chat webhook.
+    pub fn render(&self, fields: &Fields) -> ExecuteWebhook {
+        let colour = self
+            .colour
+            .map_or(colours::branding::YELLOW, |Colour(colour)| colour);
+
+        let mut author = CreateEmbedAuthor::new(self.title.render(fields));
+        let mut message = ExecuteWebhook::new();
+        if let Some(player) = self.player.as_deref().and_then(|field| fields.get(field)) {
+            let avatar = format!("https://skinatar.firstdark.dev/avatar/{player}");
+            author = author.icon_url(&avatar);
+            message = message.username(&**player).avatar_url(avatar);
+        } else {
+            message = message
+                .username("Console")
+                .avatar_url("https://skinatar.firstdark.dev/avatar/Console");
+        }
+
+        let mut embed = CreateEmbed::new().author(author).colour(colour);
+        if let Some(description) = &self.description {
+            embed = embed.description(description.render(fields));
+        }
+
+        message.embed(embed)
+    }
+
+    /// Checks that every field the pattern uses is captured, and that the pattern can be matched
+    /// unambiguously.
+    fn validate(&self) -> Result<()> {
+        let captured = self.pattern.fields().collect::<Vec<_>>();
+
+        if self
+            .pattern
+            .0
+            .windows(2)
+            .any(|pair| matches!(pair, [Segment::Field(_), Segment::Field(_)]))
+        {
+            bail!(
+                "Fields in pattern {:?} must be separated by text",
+                self.name
+            );
+        }
+
+        for (i, field) in captured.iter().enumerate() {
+            if captured[..i].contains(field) {
+                bail!("Pattern {:?} captures {field:?} twice", self.name);
+            }
+        }
+
+        let used = self
+            .title
+            .fields()
+            .chain(self.description.iter().flat_map(Template::fields))
+            .chain(self.player.as_deref());
+        for field in used {
+            if !captured.contains(&field) {
+                bail!("Pattern {:?} does not capture {field:?}", self.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads the patterns in a JSON file, which holds an array of them.
+pub fn load(path: &Path) -> Result<Box<[Pattern]>> {
+    let patterns: Box<[Pattern]> = serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| eyre!("Invalid patterns in {path:?}: {e}"))?;
+
+    for pattern in &patterns {
+        pattern
+            .validate()
+            .map_err(|e| eyre!("Invalid patterns in {path:?}: {e}"))?;
+    }
+
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn template(s: &str) -> Template {
+        s.parse().expect("the template does not parse")
+    }
+
+    fn pattern(pattern: serde_json::Value) -> Result<Pattern> {
+        let pattern: Pattern = serde_json::from_value(pattern)?;
+        pattern.validate()?;
+        Ok(pattern)
+    }
+
+    #[test]
+    fn parses_literal_templates() {
+        let template = template("Saved {{all}} chunks");
+        assert_eq!(template.fields().count(), 0);
+        assert_eq!(template.render(&Fields::new()), "Saved {all} chunks");
+
+        assert_eq!(
+            template.captures(b"Saved {all} chunks"),
+            Some(Fields::new())
+        );
+        assert_eq!(template.captures(b"Saved {all} chunks!"), None);
+        assert_eq!(template.captures(b"Saved"), None);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for s in ["{unclosed", "{}", "{not a name}", "stray }"] {
+            assert!(s.parse::<Template>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn captures_fields() {
+        let template = template("{player} found {item} in {place}");
+        let fields = template
+            .captures(b"Steve found a diamond in a cave")
+            .expect("the message does not match");
+        assert_eq!(&*fields["player"], "Steve");
+        assert_eq!(&*fields["item"], "a diamond");
+        assert_eq!(&*fields["place"], "a cave");
+
+        // Each field is as short as it can be, and the last takes the rest
+        let fields = template
+            .captures(b"Steve found Alex found gold in in a cave")
+            .expect("the message does not match");
+        assert_eq!(&*fields["player"], "Steve");
+        assert_eq!(&*fields["item"], "Alex found gold");
+        assert_eq!(&*fields["place"], "in a cave");
+
+        // Fields are never empty
+        assert_eq!(template.captures(b" found a diamond in a cave"), None);
+        assert_eq!(template.captures(b"Steve found a diamond in "), None);
+    }
+
+    #[test]
+    fn gives_up_on_lines_that_take_too_long_to_match() {
+        let template = template("{a} x {b} x {c} x {d} x {e} end");
+        let message = " x ".repeat(500);
+
+        let start = std::time::Instant::now();
+        assert_eq!(template.captures(message.as_bytes()), None);
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn validates_patterns() {
+        let valid = pattern(json!({
+            "name": "quest",
+            "pattern": "{player} completed {quest}",
+            "title": "{player} completed a quest",
+            "description": "{quest}",
+            "colour": "#f1c40f",
+            "player": "player",
+        }))
+        .expect("the pattern is valid");
+        assert!(valid.matches(b"Steve completed The Nether").is_some());
+
+        let invalid = [
+            // Adjacent fields could be split anywhere
+            json!({"name": "a", "pattern": "{player}{quest}", "title": "{player}"}),
+            // Fields used by the embed have to be captured
+            json!({"name": "b", "pattern": "{player} joined", "title": "{who} joined"}),
+            json!({"name": "c", "pattern": "{player} joined", "title": "Hi", "player": "who"}),
+            json!({"name": "d", "pattern": "{player} met {player}", "title": "Hi"}),
+            json!({"name": "e", "pattern": "{player}", "title": "Hi", "colour": "yellow"}),
+            json!({"name": "f", "pattern": "{player}", "title": "Hi", "unknown": true}),
+        ];
+        for pattern_json in invalid {
+            assert!(pattern(pattern_json.clone()).is_err(), "{pattern_json}");
+        }
+    }
+}
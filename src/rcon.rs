@@ -0,0 +1,347 @@
+use std::sync::Arc;
+
+use eyre::bail;
+use subtle::ConstantTimeEq;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{Result, instance::Instance, interface};
+
+pub const TYPE_RESPONSE: i32 = 0;
+pub const TYPE_COMMAND: i32 = 2;
+pub const TYPE_AUTH_RESPONSE: i32 = 2;
+pub const TYPE_LOGIN: i32 = 3;
+
+/// Largest packet the protocol allows.
+const MAX_PACKET: usize = 4096 + 10;
+
+/// Largest body of a response packet, with longer output split across several packets.
+const MAX_RESPONSE_BODY: usize = 4096;
+
+/// A packet of the Source RCON protocol used by Minecraft.
+#[derive(Clone, Debug)]
+pub struct Packet {
+    pub id: i32,
+    pub kind: i32,
+    pub body: Vec<u8>,
+}
+
+impl Packet {
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> Result<Self> {
+        let len = reader.read_i32_le().await?;
+        let Ok(len @ 10..=MAX_PACKET) = usize::try_from(len) else {
+            bail!("Invalid RCON packet length {len}");
+        };
+
+        let id = reader.read_i32_le().await?;
+        let kind = reader.read_i32_le().await?;
+
+        let mut body = vec![0; len - 8];
+        reader.read_exact(&mut body).await?;
+
+        // The body and the packet are both null-terminated
+        body.truncate(body.len() - 2);
+
+        Ok(Self { id, kind, body })
+    }
+
+    pub async fn write(&self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        let mut buf = Vec::with_capacity(self.body.len() + 14);
+        buf.extend_from_slice(&(self.body.len() as i32 + 10).to_le_bytes());
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        buf.extend_from_slice(&self.kind.to_le_bytes());
+        buf.extend_from_slice(&self.body);
+        buf.extend_from_slice(&[0, 0]);
+
+        writer.write_all(&buf).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// A connection to a server's RCON port.
+pub struct Client {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Client {
+    pub async fn connect(address: &str, password: &str) -> Result<Self> {
+        let mut client = Self {
+            stream: TcpStream::connect(address).await?,
+            next_id: 1,
+        };
+
+        let id = client.send(TYPE_LOGIN, password.as_bytes()).await?;
+        loop {
+            let packet = Packet::read(&mut client.stream).await?;
+            if packet.kind != TYPE_AUTH_RESPONSE {
+                continue;
+            }
+
+            if packet.id == -1 {
+                bail!("RCON authentication failed, check the password");
+            } else if packet.id == id {
+                break;
+            }
+        }
+
+        Ok(client)
+    }
+
+    /// Runs a command and returns its output.
+    pub async fn command(&mut self, command: &str) -> Result<String> {
+        let id = self.send(TYPE_COMMAND, command.as_bytes()).await?;
+        // Long output is split across several packets with nothing to mark the last one, so an
+        // empty packet is sent after the command and its answer marks the end of the output
+        let marker = self.send(TYPE_RESPONSE, b"").await?;
+
+        let mut output = Vec::new();
+        loop {
+            let packet = Packet::read(&mut self.stream).await?;
+            if packet.id == marker {
+                return Ok(String::from_utf8_lossy(&output).into_owned());
+            }
+
+            if packet.id == id && packet.kind == TYPE_RESPONSE {
+                output.extend_from_slice(&packet.body);
+            }
+        }
+    }
+
+    async fn send(&mut self, kind: i32, body: &[u8]) -> Result<i32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+
+        Packet {
+            id,
+            kind,
+            body: body.to_vec(),
+        }
+        .write(&mut self.stream)
+        .await?;
+
+        Ok(id)
+    }
+}
+
+/// Accepts RCON connections on `address`, running their commands on `server` and responding
+/// with the log lines that follow, so that RCON tools work without enabling RCON on the server.
+pub async fn listen(server: &'static Instance, address: String, password: String) -> Result<()> {
+    if password.is_empty() {
+        bail!("The RCON password for {} should not be empty", server.name);
+    }
+
+    let listener = TcpListener::bind(&address).await?;
+    eprintln!(
+        "Listening for RCON connections to {} on {address}",
+        server.name
+    );
+
+    let password: Arc<str> = password.into();
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let password = password.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(server, stream, &password).await {
+                eprintln!("Error handling RCON client {peer}: {e:?}");
+            }
+        });
+    }
+}
+
+async fn serve(server: &Instance, mut stream: TcpStream, password: &str) -> Result<()> {
+    let mut authenticated = false;
+
+    loop {
+        let packet = match Packet::read(&mut stream).await {
+            Ok(packet) => packet,
+            // The client going away is the normal way for a session to end
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof) =>
+            {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        match packet.kind {
+            TYPE_LOGIN => {
+                // Compared in constant time so the password cannot be guessed from response times
+                authenticated = packet.body.ct_eq(password.as_bytes()).into();
+                Packet {
+                    id: if authenticated { packet.id } else { -1 },
+                    kind: TYPE_AUTH_RESPONSE,
+                    body: Vec::new(),
+                }
+                .write(&mut stream)
+                .await?;
+
+                if !authenticated {
+                    return Ok(());
+                }
+            }
+            TYPE_COMMAND if authenticated => {
+                let output = match run(server, packet.body).await {
+                    Ok(output) => output,
+                    Err(e) => e.to_string(),
+                };
+
+                // Responses always have at least one packet, even if empty
+                let mut chunks = output
+                    .as_bytes()
+                    .chunks(MAX_RESPONSE_BODY)
+                    .collect::<Vec<_>>();
+                if chunks.is_empty() {
+                    chunks.push(&[]);
+                }
+
+                for chunk in chunks {
+                    Packet {
+                        id: packet.id,
+                        kind: TYPE_RESPONSE,
+                        body: chunk.to_vec(),
+                    }
+                    .write(&mut stream)
+                    .await?;
+                }
+            }
+            // Answered like the server's own RCON does, since clients send these to find the
+            // end of a command's output
+            TYPE_RESPONSE if authenticated => {
+                Packet {
+                    id: packet.id,
+                    kind: TYPE_RESPONSE,
+                    body: b"Unknown request 0".to_vec(),
+                }
+                .write(&mut stream)
+                .await?;
+            }
+            kind => bail!("Unexpected RCON packet of type {kind}"),
+        }
+    }
+}
+
+/// Runs a command received over RCON and returns its output without the log headers, like the
+/// server's own RCON does.
+async fn run(server: &Instance, command: Vec<u8>) -> Result<String> {
+    server.wait_until_running().await?;
+
+    let output = interface::output(server, command).await?;
+    Ok(output
+        .iter()
+        .map(|line| {
+            line.split_once("]: ")
+                .map_or(&**line, |(_, message)| message)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn packets_round_trip() {
+        let packet = Packet {
+            id: 7,
+            kind: TYPE_COMMAND,
+            body: b"list uuids".to_vec(),
+        };
+
+        let mut buf = Vec::new();
+        packet.write(&mut buf).await.unwrap();
+        assert_eq!(buf.len(), 4 + 10 + packet.body.len());
+
+        let read = Packet::read(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(read.id, packet.id);
+        assert_eq!(read.kind, packet.kind);
+        assert_eq!(read.body, packet.body);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_lengths() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&5i32.to_le_bytes());
+        buf.extend_from_slice(&[0; 10]);
+
+        assert!(Packet::read(&mut buf.as_slice()).await.is_err());
+    }
+
+    /// Accepts a single connection and answers it like a Minecraft server, where `big` prints
+    /// more output than fits in one packet and any other command is echoed back.
+    async fn stand_in(password: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            while let Ok(packet) = Packet::read(&mut stream).await {
+                let (id, kind, output) = match packet.kind {
+                    TYPE_LOGIN if packet.body == password.as_bytes() => {
+                        (packet.id, TYPE_AUTH_RESPONSE, Vec::new())
+                    }
+                    TYPE_LOGIN => (-1, TYPE_AUTH_RESPONSE, Vec::new()),
+                    TYPE_COMMAND if packet.body == b"big" => {
+                        (packet.id, TYPE_RESPONSE, vec![b'x'; 5000])
+                    }
+                    TYPE_COMMAND => {
+                        let mut output = b"ran ".to_vec();
+                        output.extend_from_slice(&packet.body);
+                        (packet.id, TYPE_RESPONSE, output)
+                    }
+                    kind => (
+                        packet.id,
+                        TYPE_RESPONSE,
+                        format!("Unknown request {kind:x}").into_bytes(),
+                    ),
+                };
+
+                let mut chunks = output.chunks(MAX_RESPONSE_BODY).collect::<Vec<_>>();
+                if chunks.is_empty() {
+                    chunks.push(&[]);
+                }
+                for chunk in chunks {
+                    let packet = Packet {
+                        id,
+                        kind,
+                        body: chunk.to_vec(),
+                    };
+                    if packet.write(&mut stream).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn runs_commands_after_authenticating() {
+        let address = stand_in("hunter2").await;
+        let mut client = Client::connect(&address, "hunter2").await.unwrap();
+
+        assert_eq!(client.command("list").await.unwrap(), "ran list");
+        assert_eq!(client.command("say hi").await.unwrap(), "ran say hi");
+    }
+
+    #[tokio::test]
+    async fn fails_with_the_wrong_password() {
+        let address = stand_in("hunter2").await;
+        assert!(Client::connect(&address, "hunter3").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn collects_output_split_across_packets() {
+        let address = stand_in("hunter2").await;
+        let mut client = Client::connect(&address, "hunter2").await.unwrap();
+
+        assert_eq!(client.command("big").await.unwrap(), "x".repeat(5000));
+        // The rest of the long output is not mistaken for the next command's
+        assert_eq!(client.command("list").await.unwrap(), "ran list");
+    }
+}
@@ -0,0 +1,334 @@
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeDelta, Timelike};
+use chumsky::prelude::*;
+use eyre::eyre;
+
+use crate::{ConsoleMessage, Result, instance::Instance, supervisor::Request};
+
+#[derive(Clone, Debug)]
+pub enum Action {
+    Command(Box<str>),
+    Announce(Box<str>),
+    Restart,
+}
+
+impl Action {
+    /// Parses an action, where anything starting with `@` is a directive to mcglue rather than a
+    /// command, so that a mistyped directive is not sent to the server.
+    fn parse(s: &str) -> Result<Self, String> {
+        let (directive, rest) = s
+            .split_once(char::is_whitespace)
+            .map_or((s, ""), |(directive, rest)| (directive, rest.trim()));
+
+        match (directive, rest) {
+            ("@announce", "") => Err("@announce needs a message".into()),
+            ("@announce", message) => Ok(Action::Announce(message.into())),
+            ("@restart", "") => Ok(Action::Restart),
+            ("@restart", _) => Err("@restart does not take any arguments".into()),
+            (directive, _) if directive.starts_with('@') => {
+                Err(format!("Unknown directive {directive}"))
+            }
+            _ => Ok(Action::Command(s.into())),
+        }
+    }
+}
+
+/// A crontab-style entry, with each field stored as a bitmask of the values it matches.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    pub action: Action,
+}
+
+impl Entry {
+    pub fn parser<'src>() -> impl Parser<'src, &'src str, Entry, extra::Err<Rich<'src, char>>> {
+        let whitespace = one_of(" \t").repeated().at_least(1);
+
+        group((
+            field(0, 59).then_ignore(whitespace),
+            field(0, 23).then_ignore(whitespace),
+            field(1, 31).then_ignore(whitespace),
+            field(1, 12).then_ignore(whitespace),
+            field(0, 7).then_ignore(whitespace),
+            any()
+                .repeated()
+                .at_least(1)
+                .to_slice()
+                .try_map(|action: &str, span| {
+                    Action::parse(action.trim()).map_err(|e| Rich::custom(span, e))
+                }),
+        ))
+        .map(
+            |(minutes, hours, days_of_month, months, days_of_week, action)| Entry {
+                minutes,
+                hours,
+                days_of_month,
+                months,
+                // Both 0 and 7 are Sunday
+                days_of_week: (days_of_week | (days_of_week >> 7)) & 0x7f,
+                action,
+            },
+        )
+    }
+
+    pub fn matches(&self, time: &DateTime<Local>) -> bool {
+        let bit = |mask: u64, n: u32| mask & (1 << n) != 0;
+
+        // Like cron, if both day fields are restricted then either of them matching is enough.
+        let day_of_month = bit(self.days_of_month, time.day());
+        let day_of_week = bit(self.days_of_week, time.weekday().num_days_from_sunday());
+        let day = match (
+            self.days_of_month == mask(1, 31),
+            self.days_of_week == mask(0, 6),
+        ) {
+            (true, _) => day_of_week,
+            (_, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        };
+
+        bit(self.minutes, time.minute())
+            && bit(self.hours, time.hour())
+            && bit(self.months, time.month())
+            && day
+    }
+}
+
+fn mask(start: u8, end: u8) -> u64 {
+    (start..=end).fold(0, |acc, n| acc | 1 << n)
+}
+
+fn field<'src>(
+    min: u8,
+    max: u8,
+) -> impl Parser<'src, &'src str, u64, extra::Err<Rich<'src, char>>> {
+    let number = text::int(10).try_map(move |s: &str, span| {
+        let n = s
+            .parse::<u8>()
+            .map_err(|e| Rich::custom(span, e.to_string()))?;
+
+        if n < min || n > max {
+            return Err(Rich::custom(span, format!("{n} is not within {min}-{max}")));
+        }
+
+        Ok(n)
+    });
+
+    let step = text::int(10).try_map(|s: &str, span| match s.parse::<u8>() {
+        Ok(0) => Err(Rich::custom(span, "Step must not be 0")),
+        Ok(n) => Ok(n),
+        Err(e) => Err(Rich::custom(span, e.to_string())),
+    });
+
+    let range = choice((
+        just('*').to((min, max, true)),
+        number.then(just('-').ignore_then(number).or_not()).try_map(
+            |(start, end), span| match end {
+                Some(end) if end < start => Err(Rich::custom(
+                    span,
+                    format!("{start}-{end} is not a valid range"),
+                )),
+                Some(end) => Ok((start, end, false)),
+                None => Ok((start, start, true)),
+            },
+        ),
+    ));
+
+    range
+        .then(just('/').ignore_then(step).or_not())
+        .map(move |((start, end, open), step)| {
+            // `n/step` means every `step` starting from `n`
+            let end = if open && step.is_some() { max } else { end };
+
+            (start..=end)
+                .step_by(step.unwrap_or(1) as usize)
+                .fold(0, |acc, n| acc | 1 << n)
+        })
+        .separated_by(just(','))
+        .at_least(1)
+        .collect::<Vec<u64>>()
+        .map(|masks| masks.into_iter().fold(0, |acc, m| acc | m))
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let schedule = std::fs::read_to_string(path)?;
+    let mut entries = Vec::new();
+
+    for (i, line) in schedule.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let entry = Entry::parser()
+            .parse(line)
+            .into_result()
+            .map_err(|errors| {
+                eyre!(
+                    "Invalid schedule entry on line {} of {path:?}: {}",
+                    i + 1,
+                    errors
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+pub async fn run(
+    server: &Instance,
+    entries: Vec<Entry>,
+    log_to_console: flume::Sender<ConsoleMessage>,
+) -> Result<()> {
+    let mut last_run = NaiveDateTime::MIN;
+
+    loop {
+        // Counted in absolute time, since local times are skipped or repeated when the clocks
+        // change
+        let now = Local::now();
+        let next = now + TimeDelta::seconds(60 - i64::from(now.second()))
+            - TimeDelta::nanoseconds(i64::from(now.nanosecond()));
+
+        tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+
+        // Like cron, entries are not run again in the hour repeated when the clocks go back
+        if next.naive_local() <= last_run {
+            continue;
+        }
+        last_run = next.naive_local();
+
+        for entry in entries.iter().filter(|e| e.matches(&next)) {
+            match &entry.action {
+                Action::Command(command) => {
+                    log_to_console
+                        .send(format!("[mcglue] Running scheduled command `{command}`\n").into())?;
+                    server.command(command.as_bytes()).await?;
+                }
+                Action::Announce(message) => {
+                    log_to_console.send(format!("[mcglue] Announcing \"{message}\"\n").into())?;
+                    server
+                        .command(format!(r#"tellraw @a {{"text":{message:?}}}"#).as_bytes())
+                        .await?;
+                }
+                Action::Restart => {
+                    log_to_console.send("[mcglue] Running scheduled restart\n".into())?;
+                    server.request(Request::Restart).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn entry(line: &str) -> Entry {
+        Entry::parser()
+            .parse(line)
+            .into_result()
+            .expect("the entry does not parse")
+    }
+
+    fn parse_field(min: u8, max: u8, s: &str) -> Option<u64> {
+        field(min, max).parse(s).into_result().ok()
+    }
+
+    fn bits(values: &[u8]) -> u64 {
+        values.iter().fold(0, |acc, n| acc | 1 << n)
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field(0, 59, "*"), Some(mask(0, 59)));
+        assert_eq!(parse_field(0, 59, "5"), Some(bits(&[5])));
+        assert_eq!(parse_field(0, 59, "1,5,9"), Some(bits(&[1, 5, 9])));
+        assert_eq!(parse_field(0, 59, "1-3"), Some(bits(&[1, 2, 3])));
+        assert_eq!(parse_field(0, 59, "*/15"), Some(bits(&[0, 15, 30, 45])));
+        assert_eq!(parse_field(0, 23, "1-10/3"), Some(bits(&[1, 4, 7, 10])));
+        // A single value with a step runs to the end of the field
+        assert_eq!(parse_field(0, 59, "10/20"), Some(bits(&[10, 30, 50])));
+        assert_eq!(parse_field(1, 12, "*/5"), Some(bits(&[1, 6, 11])));
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        assert_eq!(parse_field(0, 59, "60"), None);
+        assert_eq!(parse_field(1, 31, "0"), None);
+        assert_eq!(parse_field(0, 59, "5-3"), None);
+        assert_eq!(parse_field(0, 59, "*/0"), None);
+        assert_eq!(parse_field(0, 59, "1,"), None);
+        assert_eq!(parse_field(0, 59, ""), None);
+    }
+
+    #[test]
+    fn treats_0_and_7_as_sunday() {
+        assert_eq!(entry("0 0 * * 0 list").days_of_week, bits(&[0]));
+        assert_eq!(entry("0 0 * * 7 list").days_of_week, bits(&[0]));
+        assert_eq!(entry("0 0 * * 5-7 list").days_of_week, bits(&[0, 5, 6]));
+        assert_eq!(entry("0 0 * * * list").days_of_week, mask(0, 6));
+    }
+
+    #[test]
+    fn parses_actions() {
+        assert!(matches!(
+            entry("0 4 * * * @restart").action,
+            Action::Restart
+        ));
+        assert!(matches!(
+            entry("0 * * * * @announce Vote for the server!").action,
+            Action::Announce(message) if &*message == "Vote for the server!"
+        ));
+        assert!(matches!(
+            entry("*/5 * * * * save-all flush").action,
+            Action::Command(command) if &*command == "save-all flush"
+        ));
+
+        for line in [
+            "0 4 * * * @restart now",
+            "0 4 * * * @announce",
+            "0 4 * * * @reboot",
+            "0 4 * * *",
+        ] {
+            assert!(Entry::parser().parse(line).has_errors(), "{line}");
+        }
+    }
+
+    #[test]
+    fn matches_times() {
+        // 6 January 2025 was a Monday
+        let monday = Local.with_ymd_and_hms(2025, 1, 6, 4, 30, 0).unwrap();
+
+        assert!(entry("30 4 * * * list").matches(&monday));
+        assert!(entry("*/15 4 * 1 1-5 list").matches(&monday));
+        assert!(!entry("0 4 * * * list").matches(&monday));
+        assert!(!entry("30 5 * * * list").matches(&monday));
+        assert!(!entry("30 4 * 2 * list").matches(&monday));
+        assert!(!entry("30 4 * * 0,6 list").matches(&monday));
+        assert!(!entry("30 4 1 * * list").matches(&monday));
+    }
+
+    #[test]
+    fn matches_either_day_field_when_both_are_restricted() {
+        let entry = entry("0 4 1 * 1 list");
+        let first = Local.with_ymd_and_hms(2025, 1, 1, 4, 0, 0).unwrap();
+        let monday = Local.with_ymd_and_hms(2025, 1, 6, 4, 0, 0).unwrap();
+        let tuesday = Local.with_ymd_and_hms(2025, 1, 7, 4, 0, 0).unwrap();
+
+        assert!(entry.matches(&first));
+        assert!(entry.matches(&monday));
+        assert!(!entry.matches(&tuesday));
+    }
+}
@@ -0,0 +1,123 @@
+use std::{process::ExitStatus, time::Duration};
+
+use eyre::eyre;
+use poise::serenity_prelude::colours;
+use tokio::process::Child;
+
+use crate::{Result, instance::Instance, outbox::Outbox};
+
+pub struct ShutdownPolicy {
+    /// Seconds before `stop` at which players are warned, in descending order.
+    warnings: Vec<u64>,
+    stop_timeout: Duration,
+    term_timeout: Duration,
+}
+
+impl ShutdownPolicy {
+    pub fn from_env() -> Result<Self> {
+        let mut warnings = crate::env::shutdown_warnings()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<u64>()
+                    .map_err(|e| eyre!("Invalid SHUTDOWN_WARNINGS entry {s:?}: {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        warnings.sort_unstable();
+        warnings.dedup();
+        warnings.reverse();
+
+        Ok(Self {
+            warnings,
+            stop_timeout: Duration::from_secs(crate::env::shutdown_stop_timeout().unwrap_or(60)),
+            term_timeout: Duration::from_secs(crate::env::shutdown_term_timeout().unwrap_or(10)),
+        })
+    }
+}
+
+/// Warns players about the upcoming stop every time a point in [`ShutdownPolicy::warnings`] is
+/// reached, returning early if the server exits on its own.
+pub async fn countdown(
+    server: &Instance,
+    process: &mut Child,
+    webhook: &Outbox,
+    policy: &ShutdownPolicy,
+) -> Result<Option<ExitStatus>> {
+    for (i, &secs) in policy.warnings.iter().enumerate() {
+        warn(server, webhook, secs).await?;
+
+        let next = policy.warnings.get(i + 1).copied().unwrap_or(0);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(secs - next)) => {}
+            status = process.wait() => return Ok(Some(status?)),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Stops the server after counting down, escalating to SIGTERM and then SIGKILL if it does not
+/// exit in time.
+pub async fn stop(
+    server: &Instance,
+    process: &mut Child,
+    webhook: &Outbox,
+    policy: &ShutdownPolicy,
+) -> Result<ExitStatus> {
+    if let Some(status) = countdown(server, process, webhook, policy).await? {
+        return Ok(status);
+    }
+
+    eprintln!("Stopping server");
+    crate::announce(webhook, "Stopping server", colours::branding::RED);
+    server.command(*b"stop").await?;
+
+    if let Ok(status) = tokio::time::timeout(policy.stop_timeout, process.wait()).await {
+        return Ok(status?);
+    }
+
+    let message = format!(
+        "Server did not stop within {}s, terminating",
+        policy.stop_timeout.as_secs()
+    );
+    eprintln!("{message}");
+    crate::announce(webhook, message, colours::branding::RED);
+
+    #[cfg(unix)]
+    {
+        crate::supervisor::signal_group(process, libc::SIGTERM)?;
+
+        if let Ok(status) = tokio::time::timeout(policy.term_timeout, process.wait()).await {
+            return Ok(status?);
+        }
+
+        let message = format!(
+            "Server did not terminate within {}s, killing",
+            policy.term_timeout.as_secs()
+        );
+        eprintln!("{message}");
+        crate::announce(webhook, message, colours::branding::RED);
+    }
+
+    crate::supervisor::kill(process).await
+}
+
+async fn warn(server: &Instance, webhook: &Outbox, secs: u64) -> Result<()> {
+    let message = format!("Server stopping in {secs}s");
+    eprintln!("{message}");
+
+    server
+        .command(format!(r#"tellraw @a {{"text":{message:?},"color":"yellow"}}"#).as_bytes())
+        .await?;
+    server
+        .command(
+            format!(r#"title @a actionbar {{"text":{message:?},"color":"yellow"}}"#).as_bytes(),
+        )
+        .await?;
+
+    crate::announce(webhook, message, colours::branding::YELLOW);
+    Ok(())
+}
@@ -0,0 +1,664 @@
+use std::{
+    collections::VecDeque,
+    process::{ExitStatus, Stdio},
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
+};
+
+use bstr::ByteSlice;
+use eyre::bail;
+use parking_lot::Mutex;
+use poise::serenity_prelude::{ExecuteWebhook, colours};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Child,
+    sync::watch,
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{
+    ConsoleMessage, Result, Stream, announce,
+    instance::{Instance, Mode},
+    logsource::{self, LogSource},
+    rcon, shutdown,
+};
+
+/// Number of console lines kept to show when the server exits unexpectedly.
+pub const RECENT_LINES: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Stopped,
+    /// The server has been spawned but has not logged that it is done starting.
+    Starting,
+    Running,
+    Stopping,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::Stopped => "stopped",
+            State::Starting => "starting",
+            State::Running => "running",
+            State::Stopping => "stopping",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    Start,
+    /// Stop the server with a countdown.
+    Stop,
+    /// Stop the server with a countdown and start it again.
+    Restart,
+}
+
+enum Event {
+    /// The wrapper is exiting and the server should be stopped.
+    Shutdown,
+    Request(Request),
+    Exited(ExitStatus),
+    /// The server closed both stdout and stderr.
+    Eof,
+}
+
+pub async fn forward_held_commands(server: &Instance) -> Result<()> {
+    while let Ok(command) = server.held_commands.1.recv_async().await {
+        if server.wait_until_running().await.is_ok() {
+            server.command(command).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Supervisor {
+    pub server: &'static Instance,
+    pub log_to_console: flume::Sender<ConsoleMessage>,
+}
+
+impl Supervisor {
+    /// Runs the server until `exit` changes, starting it again when it crashes or when requested
+    /// to.
+    pub async fn run(self, mut exit: watch::Receiver<bool>) -> Result<()> {
+        if let Mode::Rcon { address, password } = &self.server.mode {
+            return self.attach(exit, address, password).await;
+        }
+
+        let server = self.server;
+        let webhook = &server.webhook;
+        let requests = &server.requests.1;
+
+        let mut restart_policy = RestartPolicy::from_env();
+        let shutdown_policy = shutdown::ShutdownPolicy::from_env()?;
+        let mut start = true;
+
+        'supervisor: loop {
+            if !start {
+                server.state.send_replace(State::Stopped);
+
+                tokio::select! {
+                    _ = exit.changed() => break,
+                    Ok(request) = requests.recv_async() => {
+                        if request != Request::Start {
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            start = false;
+
+            eprintln!("Starting server");
+            announce(webhook, "Starting server", colours::branding::GREEN);
+
+            let mut spawned = self.spawn()?;
+            let process = &mut spawned.process;
+            *server.process.lock() = process.id().map(|pid| (pid, Instant::now()));
+            server.state.send_replace(State::Starting);
+
+            let mut eof = false;
+            let status = loop {
+                let event = tokio::select! {
+                    _ = exit.changed() => Event::Shutdown,
+                    Ok(request) = requests.recv_async() => Event::Request(request),
+                    status = process.wait() => Event::Exited(status?),
+                    _ = &mut spawned.log_reader, if !eof => Event::Eof,
+                };
+
+                match event {
+                    Event::Shutdown => {
+                        if !matches!(process.try_wait(), Ok(Some(_))) {
+                            server.state.send_replace(State::Stopping);
+                            shutdown::stop(server, process, webhook, &shutdown_policy).await?;
+                        }
+
+                        spawned.finish(eof).await;
+                        break 'supervisor;
+                    }
+                    Event::Request(Request::Start) => {}
+                    Event::Request(Request::Stop) => {
+                        server.state.send_replace(State::Stopping);
+                        shutdown::stop(server, process, webhook, &shutdown_policy).await?;
+                        break None;
+                    }
+                    Event::Request(Request::Restart) => {
+                        eprintln!("Restarting server");
+                        announce(webhook, "Restarting server", colours::branding::YELLOW);
+
+                        server.state.send_replace(State::Stopping);
+                        shutdown::stop(server, process, webhook, &shutdown_policy).await?;
+                        start = true;
+                        break None;
+                    }
+                    Event::Eof => {
+                        // Nothing more can be relayed from the server, so make sure it exits
+                        eof = true;
+                        eprintln!("Server closed its output, waiting for it to exit");
+
+                        if let Ok(status) =
+                            tokio::time::timeout(Duration::from_secs(10), process.wait()).await
+                        {
+                            break Some(status?);
+                        }
+
+                        eprintln!("Server did not exit after closing its output, killing");
+                        break Some(kill(process).await?);
+                    }
+                    Event::Exited(status) => break Some(status),
+                }
+            };
+
+            *server.process.lock() = None;
+            spawned.finish(eof).await;
+
+            let crashed = status.is_some_and(|status| {
+                !status.success() || !server.stop_requested.load(Ordering::Relaxed)
+            });
+
+            if let Some(status) = status
+                && crashed
+            {
+                let title = describe_exit(status);
+                eprintln!("{title}");
+
+                let recent_lines = spawned
+                    .recent_lines
+                    .lock()
+                    .iter()
+                    .map(|line| &**line)
+                    .collect::<String>();
+
+                let mut embed = crate::status_embed(title, colours::branding::RED);
+                if !recent_lines.is_empty() {
+                    embed = embed.description(format!(
+                        "```\n{}\n```",
+                        tail(&recent_lines.replace("```", "`\u{200b}``"), 4000).trim_end()
+                    ));
+                }
+
+                webhook.send(
+                    ExecuteWebhook::new()
+                        .username("Console")
+                        .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+                        .embed(embed),
+                );
+            }
+
+            if !crashed || !restart_policy.enabled() {
+                if !start {
+                    eprintln!("Server stopped");
+                    announce(webhook, "Server stopped", colours::branding::RED);
+                }
+
+                continue;
+            }
+
+            let Some(delay) = restart_policy.crashed() else {
+                let crashes = restart_policy.crash_count();
+                eprintln!("Giving up after {crashes} crashes");
+                announce(
+                    webhook,
+                    format!("Giving up after {crashes} crashes"),
+                    colours::branding::RED,
+                );
+                continue;
+            };
+
+            eprintln!("Restarting server in {}s", delay.as_secs());
+            announce(
+                webhook,
+                format!("Server crashed, restarting in {}s", delay.as_secs()),
+                colours::branding::YELLOW,
+            );
+
+            server.state.send_replace(State::Stopped);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => start = true,
+                _ = exit.changed() => break,
+                Ok(request) = requests.recv_async() => start = request != Request::Stop,
+            }
+        }
+
+        server.state.send_replace(State::Stopped);
+
+        Ok(())
+    }
+
+    /// Relays a server that was started elsewhere until `exit` changes, following its log file
+    /// and sending commands over RCON, reconnecting whenever the connection is lost.
+    async fn attach(
+        self,
+        mut exit: watch::Receiver<bool>,
+        address: &str,
+        password: &str,
+    ) -> Result<()> {
+        let server = self.server;
+        let webhook = &server.webhook;
+        let log_file = server.latest_log();
+
+        let mut tasks = JoinSet::<Result<()>>::new();
+        let (lines_tx, lines_rx) = flume::unbounded();
+
+        // The server logged its version when it started, which is needed to load its language
+        let starting = logsource::find(log_file.clone(), |line| {
+            line.find(b"Starting minecraft server version ").is_some()
+        })
+        .await
+        .ok()
+        .flatten();
+        if let Some(line) = starting {
+            lines_tx.send_async((Stream::Stdout, line)).await?;
+        }
+
+        tasks.spawn(logsource::follow(log_file, lines_tx.clone()));
+        tasks.spawn(crate::read_logs(
+            server,
+            lines_rx,
+            self.log_to_console.clone(),
+            Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_LINES))),
+        ));
+
+        let mut backoff = Duration::from_secs(1);
+        let mut connected = false;
+
+        loop {
+            server.state.send_replace(State::Stopped);
+
+            let client = tokio::select! {
+                _ = exit.changed() => break,
+                client = rcon::Client::connect(address, password) => client,
+            };
+
+            let client = match client {
+                Ok(client) => client,
+                Err(e) => {
+                    if connected {
+                        connected = false;
+                        eprintln!("Lost connection to server");
+                        announce(webhook, "Lost connection to server", colours::branding::RED);
+                    }
+
+                    eprintln!("Could not connect to RCON at {address}: {e}");
+                    tokio::select! {
+                        _ = exit.changed() => break,
+                        _ = tokio::time::sleep(backoff) => {}
+                    }
+
+                    backoff = (backoff * 2).min(Duration::from_secs(60));
+                    continue;
+                }
+            };
+
+            backoff = Duration::from_secs(1);
+            if !connected {
+                connected = true;
+                eprintln!("Connected to server");
+                announce(webhook, "Connected to server", colours::branding::GREEN);
+            }
+
+            // Commands sent while disconnected are stale
+            let _ = server.commands.1.drain();
+            server.state.send_replace(State::Running);
+
+            let mut forwarder =
+                tokio::spawn(forward_rcon_commands(server, client, lines_tx.clone()));
+            let exiting = loop {
+                tokio::select! {
+                    _ = exit.changed() => break true,
+                    _ = &mut forwarder => break false,
+                    Ok(request) = server.requests.1.recv_async() => match request {
+                        Request::Stop => {
+                            server.state.send_replace(State::Stopping);
+                            server.command(*b"stop").await?;
+                        }
+                        Request::Start | Request::Restart => {
+                            eprintln!("Cannot start or restart {}, it is not started by mcglue", server.name);
+                        }
+                    },
+                }
+            };
+
+            forwarder.abort();
+            if exiting {
+                break;
+            }
+        }
+
+        // Give the log reader a moment to relay the last lines
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        tasks.shutdown().await;
+        server.state.send_replace(State::Stopped);
+
+        Ok(())
+    }
+
+    /// Spawns the server along with the tasks forwarding commands to it and relaying its output.
+    fn spawn(&self) -> Result<Spawned> {
+        let server = self.server;
+        let Mode::Spawn {
+            program,
+            args,
+            working_directory,
+        } = &server.mode
+        else {
+            bail!("{} is not started by mcglue", server.name);
+        };
+
+        // Commands sent while the server was stopped are stale, and a queued `stop` would
+        // immediately stop the new server.
+        let _ = server.commands.1.drain();
+        server.stop_requested.store(false, Ordering::Relaxed);
+
+        let mut command = tokio::process::Command::new(program);
+        if let Some(dir) = working_directory {
+            command.current_dir(dir);
+        }
+
+        // Give the server its own process group, so that signals reach the JVM even when it is
+        // started through a script or runner
+        #[cfg(unix)]
+        command.process_group(0);
+
+        // The same lines are written to the log file, so the output is not needed when following it
+        let output = || match server.log_source {
+            LogSource::Stdout => Stdio::piped(),
+            LogSource::File => Stdio::null(),
+        };
+
+        let mut process = command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(output())
+            .stderr(output())
+            .spawn()?;
+
+        let Some(mut stdin) = process.stdin.take() else {
+            bail!("Could not get child stdin")
+        };
+
+        let mut tasks = JoinSet::<Result<()>>::new();
+
+        tasks.spawn(async move {
+            while let Ok(msg) = server.commands.1.recv_async().await {
+                if msg.trim_ascii() == b"stop" {
+                    server.stop_requested.store(true, Ordering::Relaxed);
+                }
+
+                stdin.write_all(&msg).await?;
+                stdin.write_u8(b'\n').await?;
+                stdin.flush().await?;
+            }
+
+            Ok(())
+        });
+
+        let (lines_tx, lines_rx) = flume::unbounded();
+        match server.log_source {
+            LogSource::Stdout => {
+                let Some(stdout) = process.stdout.take() else {
+                    bail!("Could not get child stdout");
+                };
+
+                let Some(stderr) = process.stderr.take() else {
+                    bail!("Could not get child stderr");
+                };
+
+                tasks.spawn(crate::read_lines(stdout, Stream::Stdout, lines_tx.clone()));
+                tasks.spawn(crate::read_lines(stderr, Stream::Stderr, lines_tx));
+            }
+            // The server rotates the previous log away as it starts, which the follower picks up
+            LogSource::File => {
+                tasks.spawn(logsource::follow(server.latest_log(), lines_tx));
+            }
+        }
+
+        let recent_lines = Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_LINES)));
+        let log_reader = tokio::task::spawn(crate::read_logs(
+            server,
+            lines_rx,
+            self.log_to_console.clone(),
+            recent_lines.clone(),
+        ));
+
+        Ok(Spawned {
+            process,
+            tasks,
+            log_reader,
+            recent_lines,
+        })
+    }
+}
+
+/// A spawned server and the tasks relaying to and from it.
+struct Spawned {
+    process: Child,
+    tasks: JoinSet<Result<()>>,
+    /// Finishes once both stdout and stderr of the server are closed. A followed log file is
+    /// never closed, so it does not finish on its own.
+    log_reader: JoinHandle<Result<()>>,
+    recent_lines: Arc<Mutex<VecDeque<Box<str>>>>,
+}
+
+impl Spawned {
+    /// Gives the log reader a moment to relay the last lines of output, then stops all tasks.
+    async fn finish(&mut self, eof: bool) {
+        if !eof {
+            let _ = tokio::time::timeout(Duration::from_secs(1), &mut self.log_reader).await;
+        }
+
+        self.log_reader.abort();
+        self.tasks.shutdown().await;
+    }
+}
+
+fn describe_exit(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return format!("Server was killed by signal {signal}");
+        }
+    }
+
+    match status.code() {
+        Some(code) => format!("Server exited with code {code}"),
+        None => String::from("Server exited"),
+    }
+}
+
+/// Returns at most the last `max` bytes of `s`, starting at a line boundary if possible.
+fn tail(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+
+    let mut start = s.len() - max;
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+
+    let s = &s[start..];
+    s.find('\n').map_or(s, |idx| &s[idx + 1..])
+}
+
+pub struct RestartPolicy {
+    enabled: bool,
+    backoff: Duration,
+    backoff_max: Duration,
+    max_crashes: usize,
+    window: Duration,
+    crashes: VecDeque<Instant>,
+}
+
+impl RestartPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: crate::env::auto_restart().unwrap_or(false),
+            backoff: Duration::from_secs(crate::env::restart_backoff().unwrap_or(5)),
+            backoff_max: Duration::from_secs(crate::env::restart_backoff_max().unwrap_or(300)),
+            max_crashes: crate::env::restart_max_crashes().unwrap_or(5),
+            window: Duration::from_secs(crate::env::restart_crash_window().unwrap_or(600)),
+            crashes: VecDeque::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn crash_count(&self) -> usize {
+        self.crashes.len()
+    }
+
+    /// Records a crash and returns how long to wait before restarting the server, or `None` if
+    /// too many crashes happened within the window.
+    pub fn crashed(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        while self
+            .crashes
+            .front()
+            .is_some_and(|&t| now.duration_since(t) > self.window)
+        {
+            self.crashes.pop_front();
+        }
+
+        self.crashes.push_back(now);
+        if self.crashes.len() >= self.max_crashes {
+            return None;
+        }
+
+        let exponent = (self.crashes.len() - 1).min(16) as u32;
+        Some(
+            self.backoff
+                .saturating_mul(1 << exponent)
+                .min(self.backoff_max),
+        )
+    }
+}
+
+/// Sends `signal` to a process, or to every process in the group `-pid` if `pid` is negative.
+#[cfg(unix)]
+pub fn send_signal(pid: libc::pid_t, signal: libc::c_int) -> Result<()> {
+    // SAFETY: `kill` has no memory safety preconditions.
+    if unsafe { libc::kill(pid, signal) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+/// Sends `signal` to the process group of the server, doing nothing if it has already exited.
+#[cfg(unix)]
+pub fn signal_group(process: &Child, signal: libc::c_int) -> Result<()> {
+    match process.id() {
+        Some(pid) => send_signal(-(pid as libc::pid_t), signal),
+        None => Ok(()),
+    }
+}
+
+/// Kills the server along with everything else in its process group and waits for it to exit.
+pub async fn kill(process: &mut Child) -> Result<ExitStatus> {
+    #[cfg(unix)]
+    signal_group(process, libc::SIGKILL)?;
+    #[cfg(not(unix))]
+    process.start_kill()?;
+
+    Ok(process.wait().await?)
+}
+
+/// Sends commands to the server over RCON, relaying their output as if the server had logged it
+/// so that it reaches the console channel and anything waiting for a response.
+async fn forward_rcon_commands(
+    server: &Instance,
+    mut client: rcon::Client,
+    lines: flume::Sender<(Stream, Vec<u8>)>,
+) -> Result<()> {
+    while let Ok(command) = server.commands.1.recv_async().await {
+        let command = String::from_utf8_lossy(&command);
+        if command.trim() == "stop" {
+            server.stop_requested.store(true, Ordering::Relaxed);
+        }
+
+        let output = client.command(&command).await?;
+        let time = chrono::Local::now().format("%H:%M:%S");
+        for line in output.lines().filter(|line| !line.is_empty()) {
+            let line = format!("[{time}] [Server thread/INFO]: {line}\n");
+            lines
+                .send_async((Stream::Stdout, line.into_bytes()))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_crashes: usize) -> RestartPolicy {
+        RestartPolicy {
+            enabled: true,
+            backoff: Duration::from_secs(5),
+            backoff_max: Duration::from_secs(30),
+            max_crashes,
+            window: Duration::from_secs(600),
+            crashes: VecDeque::new(),
+        }
+    }
+
+    #[test]
+    fn doubles_the_backoff_up_to_the_maximum() {
+        let mut policy = policy(10);
+        let delays = (0..5).map(|_| policy.crashed()).collect::<Vec<_>>();
+
+        assert_eq!(
+            delays,
+            [5, 10, 20, 30, 30].map(|secs| Some(Duration::from_secs(secs)))
+        );
+        assert_eq!(policy.crash_count(), 5);
+    }
+
+    #[test]
+    fn gives_up_after_too_many_crashes() {
+        let mut policy = policy(3);
+
+        assert!(policy.crashed().is_some());
+        assert!(policy.crashed().is_some());
+        assert_eq!(policy.crashed(), None);
+    }
+
+    #[test]
+    fn forgets_crashes_outside_the_window() {
+        let mut policy = policy(3);
+        let long_ago = Instant::now() - Duration::from_secs(601);
+        policy.crashes.extend([long_ago, long_ago]);
+
+        assert_eq!(policy.crashed(), Some(Duration::from_secs(5)));
+        assert_eq!(policy.crash_count(), 1);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::{Result, instance::Instance, logsource::LogSource};
+
+/// How long to wait for another line before considering a thread dump complete.
+pub const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Sends SIGQUIT to the server and waits for the thread dump it prints.
+pub async fn request(server: &Instance, timeout: Duration) -> Result<Arc<str>> {
+    // The JVM prints thread dumps to stdout rather than the log file
+    if server.log_source != LogSource::Stdout {
+        eyre::bail!("Thread dumps are only available when reading the server's output.");
+    }
+
+    #[cfg(unix)]
+    {
+        let (tx, rx) = oneshot::channel();
+        server.thread_dumps.lock().push(tx);
+        server.signal(libc::SIGQUIT)?;
+
+        let Ok(dump) = tokio::time::timeout(timeout, rx).await else {
+            eyre::bail!("The server did not print a thread dump.");
+        };
+
+        Ok(dump?)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (server, timeout);
+        eyre::bail!("Thread dumps are only supported on Unix.");
+    }
+}
+
+/// Collects thread dumps out of the server's stdout so they are not relayed or parsed.
+pub struct Capture {
+    /// Callers waiting for the next thread dump, taken from the server.
+    waiters: &'static Mutex<Vec<oneshot::Sender<Arc<str>>>>,
+    dump: Option<String>,
+}
+
+impl Capture {
+    pub fn new(server: &'static Instance) -> Self {
+        Self {
+            waiters: &server.thread_dumps,
+            dump: None,
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.dump.is_some()
+    }
+
+    /// Returns whether the line was part of a thread dump.
+    pub fn feed(&mut self, line: &str) -> bool {
+        match &mut self.dump {
+            // Log lines from the server always start with a header
+            Some(dump) if !line.starts_with('[') => {
+                dump.push_str(line);
+                true
+            }
+            Some(_) => {
+                self.finish();
+                false
+            }
+            None if is_start(line) => {
+                self.dump = Some(String::from(line));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Hands the captured thread dump, if any, to everyone waiting for one.
+    pub fn finish(&mut self) {
+        let Some(dump) = self.dump.take() else {
+            return;
+        };
+
+        let dump: Arc<str> = dump.into();
+        let pending = std::mem::take(&mut *self.waiters.lock());
+        if pending.is_empty() {
+            eprintln!("Captured a thread dump that was not requested");
+        }
+
+        for tx in pending {
+            let _ = tx.send(dump.clone());
+        }
+    }
+}
+
+/// HotSpot starts a thread dump with a `yyyy-MM-dd HH:mm:ss` line followed by
+/// `Full thread dump ...`.
+fn is_start(line: &str) -> bool {
+    let line = line.trim_end();
+    if line.starts_with("Full thread dump ") {
+        return true;
+    }
+
+    line.len() == 19
+        && line.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 => b == b'-',
+            10 => b == b' ',
+            13 | 16 => b == b':',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+2024-05-01 12:00:00
+Full thread dump OpenJDK 64-Bit Server VM (21.0.2+13-LTS mixed mode, sharing):
+
+Threads class SMR info:
+_java_thread_list=0x00007f3c9c001f20, length=2, elements={
+0x00007f3cc4027a30, 0x00007f3cc41a6c40
+}
+
+\"Server thread\" #34 [4112] prio=5 os_prio=0 cpu=51234.56ms elapsed=3600.12s tid=0x00007f3cc41a6c40 nid=4112 waiting on condition  [0x00007f3c5a1fe000]
+   java.lang.Thread.State: TIMED_WAITING (sleeping)
+\tat java.lang.Thread.sleep0(java.base@21.0.2/Native Method)
+\tat java.lang.Thread.sleep(java.base@21.0.2/Thread.java:509)
+\tat net.minecraft.server.MinecraftServer.w_(MinecraftServer.java:869)
+\tat java.lang.Thread.runWith(java.base@21.0.2/Thread.java:1596)
+\tat java.lang.Thread.run(java.base@21.0.2/Thread.java:1583)
+
+JNI global refs: 24, weak refs: 0
+
+Heap
+ garbage-first heap   total 4194304K, used 1048576K [0x0000000700000000, 0x0000000800000000)
+";
+
+    fn capture() -> Capture {
+        Capture {
+            waiters: Box::leak(Box::default()),
+            dump: None,
+        }
+    }
+
+    #[test]
+    fn detects_the_start_of_a_thread_dump() {
+        assert!(is_start("2024-05-01 12:00:00\n"));
+        assert!(is_start(
+            "Full thread dump OpenJDK 64-Bit Server VM (21.0.2+13-LTS mixed mode, sharing):\n"
+        ));
+        assert!(!is_start("2024-05-01 12:00\n"));
+        assert!(!is_start("[12:00:00] [Server thread/INFO]: Done (4.2s)!\n"));
+    }
+
+    #[test]
+    fn captures_a_thread_dump_until_the_next_log_line() {
+        let mut capture = capture();
+        let (tx, mut rx) = oneshot::channel();
+        capture.waiters.lock().push(tx);
+
+        assert!(!capture.feed("[12:00:00] [Server thread/INFO]: Saving the game\n"));
+        for line in DUMP.split_inclusive('\n') {
+            assert!(capture.feed(line), "{line:?} was not captured");
+        }
+        assert!(rx.try_recv().is_err());
+
+        assert!(!capture.feed("[12:00:01] [Server thread/INFO]: Saved the game\n"));
+        assert!(!capture.is_capturing());
+        assert_eq!(&*rx.try_recv().unwrap(), DUMP);
+    }
+}
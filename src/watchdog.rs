@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude::ExecuteWebhook;
+
+use crate::{
+    ConsoleMessage, Result,
+    instance::Instance,
+    interface,
+    supervisor::{Request, State},
+    threaddump,
+};
+
+pub struct WatchdogPolicy {
+    interval: Duration,
+    timeout: Duration,
+    failures: u32,
+    restart: bool,
+}
+
+impl WatchdogPolicy {
+    /// Returns `None` if the watchdog is disabled.
+    pub fn from_env() -> Option<Self> {
+        let interval = crate::env::watchdog_interval()?;
+
+        Some(Self {
+            interval: Duration::from_secs(interval),
+            timeout: Duration::from_secs(crate::env::watchdog_timeout().unwrap_or(10)),
+            failures: crate::env::watchdog_failures().unwrap_or(3).max(1),
+            restart: crate::env::watchdog_restart().unwrap_or(false),
+        })
+    }
+}
+
+/// Periodically sends `list uuids` to the server and waits for the response to be parsed,
+/// treating the server as hung if it fails to respond `policy.failures` times in a row.
+pub async fn run(
+    server: &Instance,
+    policy: WatchdogPolicy,
+    log_to_console: flume::Sender<ConsoleMessage>,
+) -> Result<()> {
+    let mut state = server.state.subscribe();
+    let mut failures = 0;
+
+    loop {
+        if *state.borrow_and_update() != State::Running {
+            failures = 0;
+            *server.round_trip.lock() = None;
+            state.wait_for(|s| *s == State::Running).await?;
+        }
+
+        tokio::time::sleep(policy.interval).await;
+        if server.state() != State::Running {
+            continue;
+        }
+
+        let start = Instant::now();
+        let response = interface::list(server, policy.timeout).await;
+        if response.is_ok() {
+            *server.round_trip.lock() = Some(start.elapsed());
+
+            if failures >= policy.failures {
+                log_to_console.send("[mcglue] Server is responding again\n".into())?;
+            }
+
+            failures = 0;
+            continue;
+        }
+
+        failures += 1;
+        if failures != policy.failures {
+            continue;
+        }
+
+        let message = format!(
+            "[mcglue] Server has not responded within {}s {} times in a row, it may be hung\n",
+            policy.timeout.as_secs(),
+            failures,
+        );
+        eprint!("{message}");
+        log_to_console.send(message.into())?;
+
+        match threaddump::request(server, Duration::from_secs(10)).await {
+            Ok(dump) => log_to_console.send(ConsoleMessage::Attachment {
+                message: ExecuteWebhook::new()
+                    .username("Console")
+                    .avatar_url("https://skinatar.firstdark.dev/avatar/Console")
+                    .content("Thread dump of the unresponsive server"),
+                name: "threaddump.txt",
+                data: dump.as_bytes().into(),
+            })?,
+            Err(e) => eprintln!("Could not get a thread dump: {e}"),
+        }
+
+        if policy.restart {
+            log_to_console.send("[mcglue] Restarting the hung server\n".into())?;
+            server.request(Request::Restart).await?;
+        }
+    }
+}